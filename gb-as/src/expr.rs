@@ -0,0 +1,250 @@
+use crate::token::Operator;
+
+/// A built-in function of an expression
+#[derive(Clone, Debug, PartialEq)]
+pub enum Function {
+    /// The upper byte of a 16-bit value
+    High,
+    /// The lower byte of a 16-bit value
+    Low,
+    /// The ROM bank a label lives in
+    Bank,
+    /// The number of bytes in a section
+    Sizeof,
+    /// The address a section starts at
+    Startof
+}
+
+impl Function {
+    /// The function with the given name, if any
+    pub fn named(name: &str) -> Option<Self>
+    {
+        use Function::*;
+        Some(match name {
+            "high" => High,
+            "low" => Low,
+            "bank" => Bank,
+            "sizeof" => Sizeof,
+            "startof" => Startof,
+            _ => return None
+        })
+    }
+}
+
+/// A constant expression over values and symbols
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    /// A literal value
+    Value(i32),
+    /// The value of a symbol
+    Symbol(String),
+    /// An operator applied to a single operand
+    Unary(Operator, Box<Expr>),
+    /// An operator applied to two operands
+    Binary(Operator, Box<Expr>, Box<Expr>),
+    /// A built-in function applied to its argument
+    Call(Function, Box<Expr>)
+}
+
+/// An error found while evaluating an expression
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// A symbol with no value (yet)
+    Undefined(String),
+    /// Division or remainder by zero
+    DivideByZero,
+    /// An operator that cannot be used in this position
+    Operator(Operator),
+    /// A function argument that is not a name
+    Argument(Function),
+    /// A symbol that does not refer to a location
+    Label(String),
+    /// A section that does not exist
+    Section(String),
+    /// A symbol or section whose address is not known yet
+    Unplaced(String)
+}
+
+/// The names an expression is evaluated against
+pub trait Scope {
+    /// The value of a symbol
+    fn value(&self, name: &str) -> Result<i32, Error>;
+    /// The ROM bank of a label
+    fn bank(&self, name: &str) -> Result<i32, Error>;
+    /// The size of a section in bytes
+    fn size(&self, section: &str) -> Result<i32, Error>;
+    /// The start address of a section
+    fn start(&self, section: &str) -> Result<i32, Error>;
+}
+
+#[inline]
+fn boolean(b: bool) -> i32
+{
+    if b { 1 } else { 0 }
+}
+
+impl Expr {
+    /// Evaluate the expression, where `scope`
+    /// gives the value of each symbol.
+    pub fn eval<S>(&self, scope: &S) -> Result<i32, Error>
+        where S: Scope
+    {
+        use Operator::*;
+        Ok(match self {
+            Expr::Value(v) => *v,
+            Expr::Symbol(s) => scope.value(s)?,
+            Expr::Unary(op, e) => {
+                let v = e.eval(scope)?;
+                match op {
+                    Plus => v,
+                    Minus => v.wrapping_neg(),
+                    Tilde => !v,
+                    Bang => boolean(v == 0),
+                    _ => return Err(Error::Operator(op.clone()))
+                }
+            },
+            Expr::Binary(op, l, r) => {
+                let l = l.eval(scope)?;
+                let r = r.eval(scope)?;
+                match op {
+                    Plus => l.wrapping_add(r),
+                    Minus => l.wrapping_sub(r),
+                    Star => l.wrapping_mul(r),
+                    Slash | Percent if r == 0 => return Err(Error::DivideByZero),
+                    Slash => l.wrapping_div(r),
+                    Percent => l.wrapping_rem(r),
+                    ShiftLeft => l.wrapping_shl(r as u32),
+                    ShiftRight => l.wrapping_shr(r as u32),
+                    Ampersand => l & r,
+                    Pipe => l | r,
+                    Caret => l ^ r,
+                    Equal => boolean(l == r),
+                    NotEqual => boolean(l != r),
+                    Less => boolean(l < r),
+                    LessEqual => boolean(l <= r),
+                    Greater => boolean(l > r),
+                    GreaterEqual => boolean(l >= r),
+                    DoubleAmpersand => boolean(l != 0 && r != 0),
+                    DoublePipe => boolean(l != 0 || r != 0),
+                    Tilde | Bang => return Err(Error::Operator(op.clone()))
+                }
+            },
+            Expr::Call(f, e) => {
+                use Function::*;
+                match f {
+                    High => (e.eval(scope)? >> 8) & 0xFF,
+                    Low => e.eval(scope)? & 0xFF,
+                    Bank | Sizeof | Startof => {
+                        let name = match e.as_ref() {
+                            Expr::Symbol(s) => s,
+                            _ => return Err(Error::Argument(f.clone()))
+                        };
+                        match f {
+                            Bank => scope.bank(name)?,
+                            Sizeof => scope.size(name)?,
+                            _ => scope.start(name)?
+                        }
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Names;
+
+    impl Scope for Names {
+        fn value(&self, name: &str) -> Result<i32, Error>
+        {
+            match name {
+                "row" => Ok(2),
+                "tbl" => Ok(0x4123),
+                _ => Err(Error::Undefined(name.into()))
+            }
+        }
+
+        fn bank(&self, name: &str) -> Result<i32, Error>
+        {
+            match name {
+                "tbl" => Ok(1),
+                "row" => Err(Error::Label(name.into())),
+                _ => Err(Error::Undefined(name.into()))
+            }
+        }
+
+        fn size(&self, section: &str) -> Result<i32, Error>
+        {
+            match section {
+                "data" => Ok(0x20),
+                _ => Err(Error::Section(section.into()))
+            }
+        }
+
+        fn start(&self, section: &str) -> Result<i32, Error>
+        {
+            match section {
+                "data" => Ok(0x150),
+                _ => Err(Error::Section(section.into()))
+            }
+        }
+    }
+
+    fn value(v: i32) -> Box<Expr>
+    {
+        Box::new(Expr::Value(v))
+    }
+
+    fn symbol(s: &str) -> Box<Expr>
+    {
+        Box::new(Expr::Symbol(s.into()))
+    }
+
+    #[test]
+    fn eval()
+    {
+        let e = Expr::Binary(Operator::Plus, value(0x9800), Box::new(
+            Expr::Binary(Operator::Star, value(32), Box::new(Expr::Symbol("row".into())))
+        ));
+        assert_eq!(e.eval(&Names), Ok(0x9840));
+
+        let e = Expr::Unary(Operator::Bang, Box::new(
+            Expr::Binary(Operator::Less, value(1), value(2))
+        ));
+        assert_eq!(e.eval(&Names), Ok(0));
+
+        let e = Expr::Binary(Operator::Percent, value(1), value(0));
+        assert_eq!(e.eval(&Names), Err(Error::DivideByZero));
+
+        let e = Expr::Symbol("col".into());
+        assert_eq!(e.eval(&Names), Err(Error::Undefined("col".into())));
+    }
+
+    #[test]
+    fn functions()
+    {
+        let e = Expr::Call(Function::High, symbol("tbl"));
+        assert_eq!(e.eval(&Names), Ok(0x41));
+
+        let e = Expr::Call(Function::Low, symbol("tbl"));
+        assert_eq!(e.eval(&Names), Ok(0x23));
+
+        let e = Expr::Call(Function::Bank, symbol("tbl"));
+        assert_eq!(e.eval(&Names), Ok(1));
+
+        let e = Expr::Call(Function::Bank, symbol("row"));
+        assert_eq!(e.eval(&Names), Err(Error::Label("row".into())));
+
+        let e = Expr::Binary(Operator::Plus,
+            Box::new(Expr::Call(Function::Startof, symbol("data"))),
+            Box::new(Expr::Call(Function::Sizeof, symbol("data")))
+        );
+        assert_eq!(e.eval(&Names), Ok(0x170));
+
+        let e = Expr::Call(Function::Sizeof, value(1));
+        assert_eq!(e.eval(&Names), Err(Error::Argument(Function::Sizeof)));
+    }
+}
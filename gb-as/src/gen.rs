@@ -3,6 +3,8 @@ use crate::parse;
 use parse::{
     Unit,
     Program,
+    Section,
+    Region,
     Operand,
     Register,
     Register16,
@@ -16,38 +18,116 @@ use byteorder::{
     WriteBytesExt
 };
 
+/// The error for operands that have no encoding
+fn invalid() -> std::io::Error
+{
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid operands")
+}
+
+/// The 3-bit encoding of an 8-bit register operand, where
+/// `(hl)` takes the place of the missing register 6.
+fn reg_code(operand: &Operand) -> Result<u8, std::io::Error>
+{
+    use Register::*;
+    Ok(match operand {
+        Operand::Register(B) => 0,
+        Operand::Register(C) => 1,
+        Operand::Register(D) => 2,
+        Operand::Register(E) => 3,
+        Operand::Register(H) => 4,
+        Operand::Register(L) => 5,
+        Operand::Indirect16(Register16::HL) => 6,
+        Operand::Register(A) => 7,
+        _ => return Err(invalid())
+    })
+}
+
+fn adc_write<W>(w: &mut W, operand: &Operand) -> Result<(), std::io::Error>
+    where W: std::io::Write
+{
+    match operand {
+        Operand::Immediate8(u) => { w.write_all(&[0xCE, *u])?; },
+        Operand::Register(_) | Operand::Indirect16(Register16::HL) => {
+            w.write_all(&[0x88 | reg_code(operand)?])?;
+        },
+        _ => return Err(invalid())
+    }
+    Ok(())
+}
+
+fn add_write<W>(w: &mut W, op1: &Operand, op2: &Operand) -> Result<(), std::io::Error>
+    where W: std::io::Write
+{
+    match (op1, op2) {
+        (Operand::Register(Register::A), Operand::Immediate8(u)) => { w.write_all(&[0xC6, *u])?; },
+        (Operand::Register(Register::A), _) => { w.write_all(&[0x80 | reg_code(op2)?])?; },
+        (Operand::Register16(Register16::HL), Operand::Register16(r)) => {
+            use Register16::*;
+            match r {
+                BC => { w.write_all(&[0x09])?; },
+                DE => { w.write_all(&[0x19])?; },
+                HL => { w.write_all(&[0x29])?; },
+                SP => { w.write_all(&[0x39])?; },
+                _ => return Err(invalid())
+            }
+        },
+        (Operand::Register16(Register16::SP), Operand::Offset(o)) => { w.write_all(&[0xE8, *o as u8])?; },
+        _ => return Err(invalid())
+    }
+    Ok(())
+}
+
 fn and_write<W>(w: &mut W, operand: &Operand) -> Result<(), std::io::Error>
     where W: std::io::Write
 {
     match operand {
-        Operand::Immediate8(u) => { w.write(&[0xE6, *u])?; },
+        Operand::Immediate8(u) => { w.write_all(&[0xE6, *u])?; },
         Operand::Register(r) => {
             use Register::*;
             match r {
-                A => { w.write(&[0xA7])?; },
-                B => { w.write(&[0xA0])?; },
-                C => { w.write(&[0xA1])?; },
-                D => { w.write(&[0xA2])?; },
-                E => { w.write(&[0xA3])?; },
-                H => { w.write(&[0xA4])?; },
-                L => { w.write(&[0xA5])?; }
+                A => { w.write_all(&[0xA7])?; },
+                B => { w.write_all(&[0xA0])?; },
+                C => { w.write_all(&[0xA1])?; },
+                D => { w.write_all(&[0xA2])?; },
+                E => { w.write_all(&[0xA3])?; },
+                H => { w.write_all(&[0xA4])?; },
+                L => { w.write_all(&[0xA5])?; }
             }
         },
-        Operand::Indirect16(Register16::HL) => { w.write(&[0xA6])?; },
-        _ => unreachable!()
+        Operand::Indirect16(Register16::HL) => { w.write_all(&[0xA6])?; },
+        _ => return Err(invalid())
     }
     Ok(())
 }
 
+/// Write a 0xCB prefixed instruction, where the register
+/// (or `(hl)`) is encoded in the low bits of the opcode.
+fn cb_write<W>(w: &mut W, opcode: u8, operand: &Operand) -> Result<(), std::io::Error>
+    where W: std::io::Write
+{
+    w.write_all(&[0xCB, opcode | reg_code(operand)?])?;
+    Ok(())
+}
+
+/// Write one of the `bit`, `res` or `set` instructions
+fn bit_write<W>(w: &mut W, opcode: u8, op1: &Operand, op2: &Operand) -> Result<(), std::io::Error>
+    where W: std::io::Write
+{
+    match op1 {
+        Operand::Immediate8(n) if *n <= 7 => cb_write(w, opcode | n << 3, op2),
+        _ => Err(invalid())
+    }
+}
+
 fn call_1_write<W>(w: &mut W, operand: &Operand) -> Result<(), std::io::Error>
     where W: std::io::Write
 {
     match operand {
         Operand::Immediate16(u) => {
-            w.write(&[0xCD])?;
+            w.write_all(&[0xCD])?;
             w.write_u16::<LittleEndian>(*u)?;
         },
-        _ => unreachable!()
+        _ => return Err(invalid())
     }
     Ok(())
 }
@@ -58,15 +138,15 @@ fn call_2_write<W>(w: &mut W, op1: &Operand, op2: &Operand) -> Result<(), std::i
     match (op1, op2) {
         (Operand::Flag(f), Operand::Immediate16(u)) => {
             match f {
-                Flag::Z  => { w.write(&[0xCC])?; },
-                Flag::NZ => { w.write(&[0xC4])?; },
-                Flag::CR  => { w.write(&[0xDC])?; },
-                Flag::NC => { w.write(&[0xD4])?; },
-                _  => unreachable!()
+                Flag::Z  => { w.write_all(&[0xCC])?; },
+                Flag::NZ => { w.write_all(&[0xC4])?; },
+                Flag::CR  => { w.write_all(&[0xDC])?; },
+                Flag::NC => { w.write_all(&[0xD4])?; },
+                _  => return Err(invalid())
             }
             w.write_u16::<LittleEndian>(*u)?;
         },
-        _ => unreachable!()
+        _ => return Err(invalid())
     }
     Ok(())
 }
@@ -76,21 +156,21 @@ fn cp_write<W>(w: &mut W, operand: &Operand) -> Result<(), std::io::Error>
     where W: std::io::Write
 {
     match operand {
-        Operand::Immediate8(u) => { w.write(&[0xFE, *u])?; },
+        Operand::Immediate8(u) => { w.write_all(&[0xFE, *u])?; },
         Operand::Register(r) => {
             use Register::*;
             match r {
-                A => { w.write(&[0xBF])?; },
-                B => { w.write(&[0xB8])?; },
-                C => { w.write(&[0xB9])?; },
-                D => { w.write(&[0xBA])?; },
-                E => { w.write(&[0xBB])?; },
-                H => { w.write(&[0xBC])?; },
-                L => { w.write(&[0xBD])?; }
+                A => { w.write_all(&[0xBF])?; },
+                B => { w.write_all(&[0xB8])?; },
+                C => { w.write_all(&[0xB9])?; },
+                D => { w.write_all(&[0xBA])?; },
+                E => { w.write_all(&[0xBB])?; },
+                H => { w.write_all(&[0xBC])?; },
+                L => { w.write_all(&[0xBD])?; }
             }
         },
-        Operand::Indirect16(Register16::HL) => { w.write(&[0xBE])?; },
-        _ => unreachable!()
+        Operand::Indirect16(Register16::HL) => { w.write_all(&[0xBE])?; },
+        _ => return Err(invalid())
     }
     Ok(())
 }
@@ -102,27 +182,27 @@ fn dec_write<W>(w: &mut W, operand: &Operand) -> Result<(), std::io::Error>
         Operand::Register(r) => {
             use Register::*;
             match r {
-                A => { w.write(&[0x3D])?; },
-                B => { w.write(&[0x05])?; },
-                C => { w.write(&[0x0D])?; },
-                D => { w.write(&[0x15])?; },
-                E => { w.write(&[0x1D])?; },
-                H => { w.write(&[0x25])?; },
-                L => { w.write(&[0x2D])?; }
+                A => { w.write_all(&[0x3D])?; },
+                B => { w.write_all(&[0x05])?; },
+                C => { w.write_all(&[0x0D])?; },
+                D => { w.write_all(&[0x15])?; },
+                E => { w.write_all(&[0x1D])?; },
+                H => { w.write_all(&[0x25])?; },
+                L => { w.write_all(&[0x2D])?; }
             }
         },
         Operand::Register16(r) => {
             use Register16::*;
             match r {
-                BC => { w.write(&[0x0B])?; },
-                DE => { w.write(&[0x1B])?; },
-                HL => { w.write(&[0x2B])?; },
-                SP => { w.write(&[0x3B])?; },
-                _ => unreachable!()
+                BC => { w.write_all(&[0x0B])?; },
+                DE => { w.write_all(&[0x1B])?; },
+                HL => { w.write_all(&[0x2B])?; },
+                SP => { w.write_all(&[0x3B])?; },
+                _ => return Err(invalid())
             }
         },
-        Operand::Indirect16(Register16::HL) => { w.write(&[0x35])?; },
-        _ => unreachable!()
+        Operand::Indirect16(Register16::HL) => { w.write_all(&[0x35])?; },
+        _ => return Err(invalid())
     }
     Ok(())
 }
@@ -134,27 +214,27 @@ fn inc_write<W>(w: &mut W, operand: &Operand) -> Result<(), std::io::Error>
         Operand::Register(r) => {
             use Register::*;
             match r {
-                A => { w.write(&[0x3C])?; },
-                B => { w.write(&[0x04])?; },
-                C => { w.write(&[0x0C])?; },
-                D => { w.write(&[0x14])?; },
-                E => { w.write(&[0x1C])?; },
-                H => { w.write(&[0x24])?; },
-                L => { w.write(&[0x2C])?; }
+                A => { w.write_all(&[0x3C])?; },
+                B => { w.write_all(&[0x04])?; },
+                C => { w.write_all(&[0x0C])?; },
+                D => { w.write_all(&[0x14])?; },
+                E => { w.write_all(&[0x1C])?; },
+                H => { w.write_all(&[0x24])?; },
+                L => { w.write_all(&[0x2C])?; }
             }
         },
         Operand::Register16(r) => {
             use Register16::*;
             match r {
-                BC => { w.write(&[0x03])?; },
-                DE => { w.write(&[0x13])?; },
-                HL => { w.write(&[0x23])?; },
-                SP => { w.write(&[0x33])?; },
-                _ => unreachable!()
+                BC => { w.write_all(&[0x03])?; },
+                DE => { w.write_all(&[0x13])?; },
+                HL => { w.write_all(&[0x23])?; },
+                SP => { w.write_all(&[0x33])?; },
+                _ => return Err(invalid())
             }
         },
-        Operand::Indirect16(Register16::HL) => { w.write(&[0x34])?; },
-        _ => unreachable!()
+        Operand::Indirect16(Register16::HL) => { w.write_all(&[0x34])?; },
+        _ => return Err(invalid())
     }
     Ok(())
 }
@@ -164,11 +244,11 @@ fn jp_1_write<W>(w: &mut W, operand: &Operand) -> Result<(), std::io::Error>
 {
     match operand {
         Operand::Immediate16(u) => {
-            w.write(&[0xC3])?;
+            w.write_all(&[0xC3])?;
             w.write_u16::<LittleEndian>(*u)?;
         },
-        Operand::Indirect16(Register16::HL) => { w.write(&[0xE9])?; },
-        _ => unreachable!()
+        Operand::Indirect16(Register16::HL) => { w.write_all(&[0xE9])?; },
+        _ => return Err(invalid())
     }
     Ok(())
 }
@@ -179,15 +259,15 @@ fn jp_2_write<W>(w: &mut W, op1: &Operand, op2: &Operand) -> Result<(), std::io:
     match (op1, op2) {
         (Operand::Flag(f), Operand::Immediate16(u)) => {
             match f {
-                Flag::Z  => { w.write(&[0xCA])?; },
-                Flag::NZ => { w.write(&[0xC2])?; },
-                Flag::CR  => { w.write(&[0xDA])?; },
-                Flag::NC => { w.write(&[0xD2])?; },
-                _  => unreachable!()
+                Flag::Z  => { w.write_all(&[0xCA])?; },
+                Flag::NZ => { w.write_all(&[0xC2])?; },
+                Flag::CR  => { w.write_all(&[0xDA])?; },
+                Flag::NC => { w.write_all(&[0xD2])?; },
+                _  => return Err(invalid())
             }
             w.write_u16::<LittleEndian>(*u)?;
         },
-        _ => unreachable!()
+        _ => return Err(invalid())
     }
     Ok(())
 }
@@ -196,8 +276,8 @@ fn jr_1_write<W>(w: &mut W, operand: &Operand) -> Result<(), std::io::Error>
     where W: std::io::Write
 {
     match operand {
-        Operand::Immediate8(u) => { w.write(&[0x18, *u])?; },
-        _ => unreachable!()
+        Operand::Immediate8(u) => { w.write_all(&[0x18, *u])?; },
+        _ => return Err(invalid())
     }
     Ok(())
 }
@@ -208,15 +288,80 @@ fn jr_2_write<W>(w: &mut W, op1: &Operand, op2: &Operand) -> Result<(), std::io:
     match (op1, op2) {
         (Operand::Flag(f), Operand::Immediate8(u)) => {
             match f {
-                Flag::Z  => { w.write(&[0x28])?; },
-                Flag::NZ => { w.write(&[0x20])?; },
-                Flag::CR  => { w.write(&[0x38])?; },
-                Flag::NC => { w.write(&[0x30])?; },
-                _  => unreachable!()
+                Flag::Z  => { w.write_all(&[0x28])?; },
+                Flag::NZ => { w.write_all(&[0x20])?; },
+                Flag::CR  => { w.write_all(&[0x38])?; },
+                Flag::NC => { w.write_all(&[0x30])?; },
+                _  => return Err(invalid())
             }
-            w.write(&[*u])?;
+            w.write_all(&[*u])?;
         },
-        _ => unreachable!()
+        _ => return Err(invalid())
+    }
+    Ok(())
+}
+
+fn ld_write<W>(w: &mut W, op1: &Operand, op2: &Operand) -> Result<(), std::io::Error>
+    where W: std::io::Write
+{
+    use Register16::*;
+    match (op1, op2) {
+        (Operand::Register(_), Operand::Register(_)) |
+        (Operand::Register(_), Operand::Indirect16(HL)) |
+        (Operand::Indirect16(HL), Operand::Register(_)) => {
+            w.write_all(&[0x40 | reg_code(op1)? << 3 | reg_code(op2)?])?;
+        },
+        (Operand::Register(_), Operand::Immediate8(u)) |
+        (Operand::Indirect16(HL), Operand::Immediate8(u)) => {
+            w.write_all(&[0x06 | reg_code(op1)? << 3, *u])?;
+        },
+        (Operand::Register(Register::A), Operand::Indirect16(r)) => {
+            match r {
+                BC => { w.write_all(&[0x0A])?; },
+                DE => { w.write_all(&[0x1A])?; },
+                _ => return Err(invalid())
+            }
+        },
+        (Operand::Indirect16(r), Operand::Register(Register::A)) => {
+            match r {
+                BC => { w.write_all(&[0x02])?; },
+                DE => { w.write_all(&[0x12])?; },
+                _ => return Err(invalid())
+            }
+        },
+        (Operand::Register16(r), Operand::Immediate16(u)) => {
+            match r {
+                BC => { w.write_all(&[0x01])?; },
+                DE => { w.write_all(&[0x11])?; },
+                HL => { w.write_all(&[0x21])?; },
+                SP => { w.write_all(&[0x31])?; },
+                _ => return Err(invalid())
+            }
+            w.write_u16::<LittleEndian>(*u)?;
+        },
+        (Operand::Register(Register::A), Operand::Address(u)) => {
+            w.write_all(&[0xFA])?;
+            w.write_u16::<LittleEndian>(*u)?;
+        },
+        (Operand::Address(u), Operand::Register(Register::A)) => {
+            w.write_all(&[0xEA])?;
+            w.write_u16::<LittleEndian>(*u)?;
+        },
+        (Operand::Register16(SP), Operand::Register16(HL)) => { w.write_all(&[0xF9])?; },
+        (Operand::Register16(HL), Operand::Offset(o)) => { w.write_all(&[0xF8, *o as u8])?; },
+        (Operand::Address(u), Operand::Register16(SP)) => {
+            w.write_all(&[0x08])?;
+            w.write_u16::<LittleEndian>(*u)?;
+        },
+        (Operand::High(u), Operand::Register(Register::A)) => { w.write_all(&[0xE0, *u])?; },
+        (Operand::Register(Register::A), Operand::High(u)) => { w.write_all(&[0xF0, *u])?; },
+        (Operand::Indirect(Register::C), Operand::Register(Register::A)) => { w.write_all(&[0xE2])?; },
+        (Operand::Register(Register::A), Operand::Indirect(Register::C)) => { w.write_all(&[0xF2])?; },
+        (Operand::IndirectIncrement, Operand::Register(Register::A)) => { w.write_all(&[0x22])?; },
+        (Operand::Register(Register::A), Operand::IndirectIncrement) => { w.write_all(&[0x2A])?; },
+        (Operand::IndirectDecrement, Operand::Register(Register::A)) => { w.write_all(&[0x32])?; },
+        (Operand::Register(Register::A), Operand::IndirectDecrement) => { w.write_all(&[0x3A])?; },
+        _ => return Err(invalid())
     }
     Ok(())
 }
@@ -225,21 +370,21 @@ fn or_write<W>(w: &mut W, operand: &Operand) -> Result<(), std::io::Error>
     where W: std::io::Write
 {
     match operand {
-        Operand::Immediate8(u) => { w.write(&[0xF6, *u])?; },
+        Operand::Immediate8(u) => { w.write_all(&[0xF6, *u])?; },
         Operand::Register(r) => {
             use Register::*;
             match r {
-                A => { w.write(&[0xB7])?; },
-                B => { w.write(&[0xB0])?; },
-                C => { w.write(&[0xB1])?; },
-                D => { w.write(&[0xB2])?; },
-                E => { w.write(&[0xB3])?; },
-                H => { w.write(&[0xB4])?; },
-                L => { w.write(&[0xB5])?; }
+                A => { w.write_all(&[0xB7])?; },
+                B => { w.write_all(&[0xB0])?; },
+                C => { w.write_all(&[0xB1])?; },
+                D => { w.write_all(&[0xB2])?; },
+                E => { w.write_all(&[0xB3])?; },
+                H => { w.write_all(&[0xB4])?; },
+                L => { w.write_all(&[0xB5])?; }
             }
         },
-        Operand::Indirect16(Register16::HL) => { w.write(&[0xB6])?; },
-        _ => unreachable!()
+        Operand::Indirect16(Register16::HL) => { w.write_all(&[0xB6])?; },
+        _ => return Err(invalid())
     }
     Ok(())
 }
@@ -248,11 +393,11 @@ fn pop_write<W>(w: &mut W, operand: &Operand) -> Result<(), std::io::Error>
     where W: std::io::Write
 {
     match operand {
-        Operand::Register16(Register16::AF) => { w.write(&[0xF1])?; },
-        Operand::Register16(Register16::BC) => { w.write(&[0xC1])?; },
-        Operand::Register16(Register16::DE) => { w.write(&[0xD1])?; },
-        Operand::Register16(Register16::HL) => { w.write(&[0xE1])?; },
-        _ => unreachable!()
+        Operand::Register16(Register16::AF) => { w.write_all(&[0xF1])?; },
+        Operand::Register16(Register16::BC) => { w.write_all(&[0xC1])?; },
+        Operand::Register16(Register16::DE) => { w.write_all(&[0xD1])?; },
+        Operand::Register16(Register16::HL) => { w.write_all(&[0xE1])?; },
+        _ => return Err(invalid())
     }
     Ok(())
 }
@@ -261,11 +406,11 @@ fn push_write<W>(w: &mut W, operand: &Operand) -> Result<(), std::io::Error>
     where W: std::io::Write
 {
     match operand {
-        Operand::Register16(Register16::AF) => { w.write(&[0xF5])?; },
-        Operand::Register16(Register16::BC) => { w.write(&[0xC5])?; },
-        Operand::Register16(Register16::DE) => { w.write(&[0xD5])?; },
-        Operand::Register16(Register16::HL) => { w.write(&[0xE5])?; },
-        _ => unreachable!()
+        Operand::Register16(Register16::AF) => { w.write_all(&[0xF5])?; },
+        Operand::Register16(Register16::BC) => { w.write_all(&[0xC5])?; },
+        Operand::Register16(Register16::DE) => { w.write_all(&[0xD5])?; },
+        Operand::Register16(Register16::HL) => { w.write_all(&[0xE5])?; },
+        _ => return Err(invalid())
     }
     Ok(())
 }
@@ -274,11 +419,11 @@ fn ret_1_write<W>(w: &mut W, operand: &Operand) -> Result<(), std::io::Error>
     where W: std::io::Write
 {
     match operand {
-        Operand::Flag(Flag::Z)  => { w.write(&[0xC8])?; },
-        Operand::Flag(Flag::NZ) => { w.write(&[0xC0])?; },
-        Operand::Flag(Flag::CR)  => { w.write(&[0xD8])?; },
-        Operand::Flag(Flag::NC) => { w.write(&[0xD0])?; },
-        _ => unreachable!()
+        Operand::Flag(Flag::Z)  => { w.write_all(&[0xC8])?; },
+        Operand::Flag(Flag::NZ) => { w.write_all(&[0xC0])?; },
+        Operand::Flag(Flag::CR)  => { w.write_all(&[0xD8])?; },
+        Operand::Flag(Flag::NC) => { w.write_all(&[0xD0])?; },
+        _ => return Err(invalid())
     }
     Ok(())
 }
@@ -287,104 +432,221 @@ fn xor_write<W>(w: &mut W, operand: &Operand) -> Result<(), std::io::Error>
     where W: std::io::Write
 {
     match operand {
-        Operand::Immediate8(u) => { w.write(&[0xEE, *u])?; },
+        Operand::Immediate8(u) => { w.write_all(&[0xEE, *u])?; },
         Operand::Register(r) => {
             use Register::*;
             match r {
-                A => { w.write(&[0xAF])?; },
-                B => { w.write(&[0xA8])?; },
-                C => { w.write(&[0xA9])?; },
-                D => { w.write(&[0xAA])?; },
-                E => { w.write(&[0xAB])?; },
-                H => { w.write(&[0xAC])?; },
-                L => { w.write(&[0xAD])?; }
+                A => { w.write_all(&[0xAF])?; },
+                B => { w.write_all(&[0xA8])?; },
+                C => { w.write_all(&[0xA9])?; },
+                D => { w.write_all(&[0xAA])?; },
+                E => { w.write_all(&[0xAB])?; },
+                H => { w.write_all(&[0xAC])?; },
+                L => { w.write_all(&[0xAD])?; }
             }
         },
-        Operand::Indirect16(Register16::HL) => { w.write(&[0xAE])?; },
-        _ => unreachable!()
+        Operand::Indirect16(Register16::HL) => { w.write_all(&[0xAE])?; },
+        _ => return Err(invalid())
+    }
+    Ok(())
+}
+
+fn rst_write<W>(w: &mut W, operand: &Operand) -> Result<(), std::io::Error>
+    where W: std::io::Write
+{
+    match operand {
+        Operand::Immediate8(u) if *u & !0x38 == 0 => { w.write_all(&[0xC7 | *u])?; },
+        _ => return Err(invalid())
     }
     Ok(())
 }
 
-fn write_ntimes<W>(w: &mut W, count: usize, byte: u8) -> Result<usize, std::io::Error>
+fn sbc_write<W>(w: &mut W, operand: &Operand) -> Result<(), std::io::Error>
+    where W: std::io::Write
+{
+    match operand {
+        Operand::Immediate8(u) => { w.write_all(&[0xDE, *u])?; },
+        Operand::Register(_) | Operand::Indirect16(Register16::HL) => {
+            w.write_all(&[0x98 | reg_code(operand)?])?;
+        },
+        _ => return Err(invalid())
+    }
+    Ok(())
+}
+
+fn sub_write<W>(w: &mut W, operand: &Operand) -> Result<(), std::io::Error>
+    where W: std::io::Write
+{
+    match operand {
+        Operand::Immediate8(u) => { w.write_all(&[0xD6, *u])?; },
+        Operand::Register(_) | Operand::Indirect16(Register16::HL) => {
+            w.write_all(&[0x90 | reg_code(operand)?])?;
+        },
+        _ => return Err(invalid())
+    }
+    Ok(())
+}
+
+fn write_ntimes<W>(w: &mut W, count: usize, byte: u8) -> Result<(), std::io::Error>
     where W: std::io::Write
 {
     const MAX: usize = 0xFF;
     if count <= MAX {
         let bytes = vec![byte; count];
-        w.write(bytes.as_slice())
+        w.write_all(bytes.as_slice())
     } else {
         let bytes = vec![byte; MAX];
         let times = count / MAX;
         let rem = count % MAX;
-        for n in 1..=times {
-            w.write(&bytes)?;
+        for _ in 1..=times {
+            w.write_all(&bytes)?;
         }
         let bytes = vec![byte; rem];
-        w.write(&bytes)
+        w.write_all(&bytes)
+    }
+}
+
+fn instruction_write<W>(w: &mut W, i: &Instruction) -> Result<(), std::io::Error>
+    where W: std::io::Write
+{
+    use Instruction::*;
+    match i {
+        Adc(_, o) => adc_write(w, o)?,
+        Add(o1, o2) => add_write(w, o1, o2)?,
+        And(o) => and_write(w, o)?,
+        Bit(o1, o2) => bit_write(w, 0x40, o1, o2)?,
+        Call_1(o) => call_1_write(w, o)?,
+        Call_2(o1, o2) => call_2_write(w, o1, o2)?,
+        Ccf => { w.write_all(&[0x3F])?; },
+        Cp(o) => cp_write(w, o)?,
+        Cpl => { w.write_all(&[0x2F])?; },
+        Daa => { w.write_all(&[0x27])?; },
+        Dec(o) => dec_write(w, o)?,
+        Di => { w.write_all(&[0xF3])?; },
+        Ei => { w.write_all(&[0xFB])?; },
+        Halt => { w.write_all(&[0x76])?; },
+        Inc(o) => inc_write(w, o)?,
+        Jp_1(o) => jp_1_write(w, o)?,
+        Jp_2(o1, o2) => jp_2_write(w, o1, o2)?,
+        Jr_1(o) => jr_1_write(w, o)?,
+        Jr_2(o1, o2) => jr_2_write(w, o1, o2)?,
+        Ld(o1, o2) => ld_write(w, o1, o2)?,
+        Nop => { w.write_all(&[0x00])?; },
+        Or(o) => or_write(w, o)?,
+        Pop(o) => pop_write(w, o)?,
+        Push(o) => push_write(w, o)?,
+        Ret => { w.write_all(&[0xC9])?; },
+        Ret_1(o) => ret_1_write(w, o)?,
+        Res(o1, o2) => bit_write(w, 0x80, o1, o2)?,
+        Reti => { w.write_all(&[0xD9])?; },
+        Rl(o) => cb_write(w, 0x10, o)?,
+        Rla => { w.write_all(&[0x17])?; },
+        Rlc(o) => cb_write(w, 0x00, o)?,
+        Rlca => { w.write_all(&[0x07])?; },
+        Rr(o) => cb_write(w, 0x18, o)?,
+        Rra => { w.write_all(&[0x1F])?; },
+        Rrc(o) => cb_write(w, 0x08, o)?,
+        Rrca => { w.write_all(&[0x0F])?; },
+        Rst(o) => rst_write(w, o)?,
+        Sbc(_, o) => sbc_write(w, o)?,
+        Scf => { w.write_all(&[0x37])?; },
+        Set(o1, o2) => bit_write(w, 0xC0, o1, o2)?,
+        Sla(o) => cb_write(w, 0x20, o)?,
+        Sra(o) => cb_write(w, 0x28, o)?,
+        Srl(o) => cb_write(w, 0x38, o)?,
+        Stop => { w.write_all(&[0x10, 0x00])?; },
+        Sub(o) => sub_write(w, o)?,
+        Swap(o) => cb_write(w, 0x30, o)?,
+        Xor(o) => xor_write(w, o)?
     }
+    Ok(())
 }
 
+/// Write a value of a data directive
+fn data_write<W>(w: &mut W, op: &Operand) -> Result<(), std::io::Error>
+    where W: std::io::Write
+{
+    match op {
+        Operand::Immediate8(b) => w.write_all(&[*b])?,
+        Operand::Immediate16(u) => w.write_u16::<LittleEndian>(*u)?,
+        Operand::Far(bank, u) => {
+            w.write_u16::<LittleEndian>(*u)?;
+            w.write_all(&[*bank])?;
+        },
+        _ => return Err(invalid())
+    }
+    Ok(())
+}
+
+/// Write every section in ROM at its position in the image, with any gaps zeroed
 pub fn write<W>(w: &mut W, program: &Program) -> Result<(), std::io::Error>
     where W: std::io::Write
 {
-    for unit in &program.units {
+    let mut sections: Vec<_> = program.sections.iter()
+        .filter(|s| s.region() == Region::Rom && !s.units.is_empty())
+        .collect();
+    sections.sort_by_key(|s| s.start());
+
+    let mut loc = 0u32;
+    for section in sections {
+        let start = section.start();
+        write_ntimes(w, start.saturating_sub(loc) as usize, 0x00)?;
+        write_section(w, section)?;
+        loc = loc.max(start + section.location() as u32);
+    }
+    Ok(())
+}
+
+/// Write the bytes of a section, where a section in RAM has none
+pub fn write_section<W>(w: &mut W, section: &Section) -> Result<(), std::io::Error>
+    where W: std::io::Write
+{
+    if section.region() != Region::Rom {
+        return Ok(());
+    }
+
+    for unit in &section.units {
         match unit {
             Unit::Instruction(i) => {
-                use Instruction::*;
-                match i {
-                    And(o) => and_write(w, o)?,
-                    Call_1(o) => call_1_write(w, o)?,
-                    Call_2(o1, o2) => call_2_write(w, o1, o2)?,
-                    Ccf => { w.write(&[0x3F])?; },
-                    Cpl => { w.write(&[0x2F])?; },
-                    Daa => { w.write(&[0x27])?; },
-                    Dec(o) => dec_write(w, o)?,
-                    Di => { w.write(&[0xF3])?; },
-                    Ei => { w.write(&[0xFB])?; },
-                    Halt => { w.write(&[0x76])?; },
-                    Inc(o) => inc_write(w, o)?,
-                    Jp_1(o) => jp_1_write(w, o)?,
-                    Jp_2(o1, o2) => jp_2_write(w, o1, o2)?,
-                    Jr_1(o) => jr_1_write(w, o)?,
-                    Jr_2(o1, o2) => jr_2_write(w, o1, o2)?,
-                    Nop => { w.write(&[0x00])?; },
-                    Or(o) => or_write(w, o)?,
-                    Pop(o) => pop_write(w, o)?,
-                    Push(o) => push_write(w, o)?,
-                    Ret => { w.write(&[0xC9])?; },
-                    Ret_1(o) => ret_1_write(w, o)?,
-                    Reti => { w.write(&[0xD9])?; },
-                    Scf => { w.write(&[0x37])?; },
-                    Stop => { w.write(&[0x10, 0x00])?; },
-                    Xor(o) => xor_write(w, o)?,
-                    _ => ()
-                }
+                instruction_write(w, i).map_err(|e| match e.kind() {
+                    std::io::ErrorKind::InvalidInput => {
+                        std::io::Error::new(e.kind(), format!("cannot encode {:?}", i))
+                    },
+                    _ => e
+                })?;
             },
 
             Unit::Directive(d) => {
                 use Directive::*;
                 match d {
-                    Ascii(string) | Utf8(string) => {
-                        w.write(&string)?;
+                    Ascii(string) | Utf8(string) | Incbin(string) => {
+                        w.write_all(string)?;
                     },
                     Asciz(string) => {
-                        w.write(&string)?;
-                        w.write(&[0x00]);
+                        w.write_all(string)?;
+                        w.write_all(&[0x00])?;
                     },
                     Byte(bytes) => {
                         match bytes {
-                            None => { w.write(&[0x00])?; },
-                            Some(bytes) => { w.write(bytes)?; }
+                            None => { w.write_all(&[0x00])?; },
+                            Some(bytes) => for b in bytes {
+                                data_write(w, b)?;
+                            }
+                        }
+                    },
+                    Word(values) | Long(values) => {
+                        for v in values {
+                            data_write(w, v)?;
                         }
                     },
                     Fill(size, byte) | Org(size, byte) => {
                         write_ntimes(w, *size, *byte)?;
+                    },
+                    Space(size) => {
+                        write_ntimes(w, *size, 0x00)?;
                     }
                 }
-            },
-
-            _ => ()
+            }
         }
     }
     Ok(())
@@ -394,6 +656,16 @@ pub fn write<W>(w: &mut W, program: &Program) -> Result<(), std::io::Error>
 mod tests {
     use super::*;
     use crate::token;
+    use std::collections::HashSet;
+
+    fn assemble(source: &str) -> Vec<u8>
+    {
+        let input = token::scan(source).unwrap();
+        let (program, _) = parse::parse(HashSet::new(), input).unwrap();
+        let mut bytes: Vec<u8> = Vec::new();
+        write(&mut bytes, &program).unwrap();
+        bytes
+    }
 
     #[test]
     fn codegen()
@@ -420,7 +692,7 @@ mod tests {
                 .utf8 "世界"
         "#).unwrap();
 
-        let program = parse::parse(input).unwrap();
+        let (program, _) = parse::parse(HashSet::new(), input).unwrap();
         let mut bytes: Vec<u8> = Vec::new();
         write(&mut bytes, &program);
         println!("{:?}", bytes);
@@ -439,9 +711,459 @@ mod tests {
                 nop
         "#).unwrap();
 
-        let program = parse::parse(input).unwrap();
+        let (program, _) = parse::parse(HashSet::new(), input).unwrap();
         let mut bytes: Vec<u8> = Vec::new();
         write(&mut bytes, &program);
         println!("{:?}", bytes);
     }
+
+    #[test]
+    fn load()
+    {
+        let bytes = assemble(r#"
+            ld b, c
+            ld a, a
+            ld (hl), e
+            ld l, (hl)
+            ld d, 0x12
+            ld (hl), 0x34
+            ld a, (bc)
+            ld a, (de)
+            ld (bc), a
+            ld (de), a
+            ld bc, 0x1234
+            ld de, 0x1234
+            ld hl, 0x1234
+            ld sp, 0xFFFE
+            ld a, (0xC000)
+            ld [0xC000], a
+            ld sp, hl
+            ld (0xC000), sp
+        "#);
+        assert_eq!(bytes, [
+            0x41, 0x7F, 0x73, 0x6E,
+            0x16, 0x12, 0x36, 0x34,
+            0x0A, 0x1A, 0x02, 0x12,
+            0x01, 0x34, 0x12, 0x11, 0x34, 0x12,
+            0x21, 0x34, 0x12, 0x31, 0xFE, 0xFF,
+            0xFA, 0x00, 0xC0, 0xEA, 0x00, 0xC0,
+            0xF9, 0x08, 0x00, 0xC0
+        ]);
+    }
+
+    #[test]
+    fn arithmetic()
+    {
+        let bytes = assemble(r#"
+            add a, b
+            add a, (hl)
+            add a, 0x10
+            adc a, a
+            adc a, 0x20
+            sub c
+            sub a, (hl)
+            sub 0x30
+            sbc a, l
+            sbc a, 0x40
+            add hl, bc
+            add hl, sp
+            add sp, 0x02
+        "#);
+        assert_eq!(bytes, [
+            0x80, 0x86, 0xC6, 0x10,
+            0x8F, 0xCE, 0x20,
+            0x91, 0x96, 0xD6, 0x30,
+            0x9D, 0xDE, 0x40,
+            0x09, 0x39, 0xE8, 0x02
+        ]);
+    }
+
+    #[test]
+    fn prefix_cb()
+    {
+        let bytes = assemble(r#"
+            rlc b
+            rrc c
+            rl d
+            rr e
+            sla h
+            sra l
+            swap (hl)
+            srl a
+            bit 0, b
+            bit 7, a
+            res 3, (hl)
+            set 5, c
+        "#);
+        assert_eq!(bytes, [
+            0xCB, 0x00, 0xCB, 0x09, 0xCB, 0x12, 0xCB, 0x1B,
+            0xCB, 0x24, 0xCB, 0x2D, 0xCB, 0x36, 0xCB, 0x3F,
+            0xCB, 0x40, 0xCB, 0x7F, 0xCB, 0x9E, 0xCB, 0xE9
+        ]);
+    }
+
+    #[test]
+    fn relative_jump()
+    {
+        let bytes = assemble(r#"
+            loop:
+                jr z, loop
+                jr nc, done
+                jr loop
+            done:
+        "#);
+        assert_eq!(bytes, [0x28, 0xFE, 0x30, 0x02, 0x18, 0xFA]);
+    }
+
+    #[test]
+    fn conditional_jump()
+    {
+        let bytes = assemble(r#"
+            start:
+                call nz, func
+                call c, func
+                jp z, start
+                jp nc, func
+                jp hl
+                jp (hl)
+            func:
+                ret c
+                ret
+        "#);
+        assert_eq!(bytes, [
+            0xC4, 0x0E, 0x00, 0xDC, 0x0E, 0x00,
+            0xCA, 0x00, 0x00, 0xD2, 0x0E, 0x00,
+            0xE9, 0xE9, 0xD8, 0xC9
+        ]);
+    }
+
+    #[test]
+    fn high_page()
+    {
+        let bytes = assemble(r#"
+            ldh (0xFF44), a
+            ldh a, [0xFF80]
+            ld (0xFF00 + 0x40), a
+            ld a, (0xFF00 + 0x41)
+            ld (c), a
+            ld a, (0xFF00 + c)
+            ldh a, (c)
+            rst 0x00
+            rst 0x38
+        "#);
+        assert_eq!(bytes, [
+            0xE0, 0x44, 0xF0, 0x80,
+            0xE0, 0x40, 0xF0, 0x41,
+            0xE2, 0xF2, 0xF2,
+            0xC7, 0xFF
+        ]);
+    }
+
+    #[test]
+    fn load_increment()
+    {
+        let bytes = assemble(r#"
+            ld (hl+), a
+            ld a, (hl+)
+            ld (hl-), a
+            ld a, [hl-]
+            ld (hli), a
+            ld a, (hld)
+            ldi (hl), a
+            ldi a, (hl)
+            ldd (hl), a
+            ldd a, (hl)
+        "#);
+        assert_eq!(bytes, [0x22, 0x2A, 0x32, 0x3A, 0x22, 0x3A, 0x22, 0x2A, 0x32, 0x3A]);
+    }
+
+    #[test]
+    fn logic()
+    {
+        let bytes = assemble(r#"
+            and 0x0F
+            or (hl)
+            xor 0xFF
+            cp b
+            cp 0x10
+        "#);
+        assert_eq!(bytes, [0xE6, 0x0F, 0xB6, 0xEE, 0xFF, 0xB8, 0xFE, 0x10]);
+    }
+
+    #[test]
+    fn stack_offset()
+    {
+        let bytes = assemble(r#"
+            add sp, -2
+            ld hl, sp+0x10
+            ld hl, sp-1
+            ld (0xFFF0), sp
+        "#);
+        assert_eq!(bytes, [0xE8, 0xFE, 0xF8, 0x10, 0xF8, 0xFF, 0x08, 0xF0, 0xFF]);
+    }
+
+    const R8: [&str; 8] = ["b", "c", "d", "e", "h", "l", "(hl)", "a"];
+    const R16: [&str; 4] = ["bc", "de", "hl", "sp"];
+
+    /// Every instruction of the base and CB tables with its encoding
+    fn opcodes() -> Vec<(String, Vec<u8>)>
+    {
+        let mut v: Vec<(String, Vec<u8>)> = vec![
+            ("nop", vec![0x00]), ("ld (bc), a", vec![0x02]), ("rlca", vec![0x07]),
+            ("ld (0x1234), sp", vec![0x08, 0x34, 0x12]), ("ld a, (bc)", vec![0x0A]),
+            ("rrca", vec![0x0F]), ("stop", vec![0x10, 0x00]), ("ld (de), a", vec![0x12]),
+            ("rla", vec![0x17]), ("jr 2", vec![0x18, 0x00]), ("ld a, (de)", vec![0x1A]),
+            ("rra", vec![0x1F]), ("jr nz, 2", vec![0x20, 0x00]), ("ld (hl+), a", vec![0x22]),
+            ("daa", vec![0x27]), ("jr z, 2", vec![0x28, 0x00]), ("ld a, (hl+)", vec![0x2A]),
+            ("cpl", vec![0x2F]), ("jr nc, 2", vec![0x30, 0x00]), ("ld (hl-), a", vec![0x32]),
+            ("scf", vec![0x37]), ("jr c, 2", vec![0x38, 0x00]), ("ld a, (hl-)", vec![0x3A]),
+            ("ccf", vec![0x3F]), ("halt", vec![0x76]),
+            ("ret nz", vec![0xC0]), ("pop bc", vec![0xC1]), ("jp nz, 0x1234", vec![0xC2, 0x34, 0x12]),
+            ("jp 0x1234", vec![0xC3, 0x34, 0x12]), ("call nz, 0x1234", vec![0xC4, 0x34, 0x12]),
+            ("push bc", vec![0xC5]), ("add a, 0x12", vec![0xC6, 0x12]), ("ret z", vec![0xC8]),
+            ("ret", vec![0xC9]), ("jp z, 0x1234", vec![0xCA, 0x34, 0x12]),
+            ("call z, 0x1234", vec![0xCC, 0x34, 0x12]), ("call 0x1234", vec![0xCD, 0x34, 0x12]),
+            ("adc a, 0x12", vec![0xCE, 0x12]), ("ret nc", vec![0xD0]), ("pop de", vec![0xD1]),
+            ("jp nc, 0x1234", vec![0xD2, 0x34, 0x12]), ("call nc, 0x1234", vec![0xD4, 0x34, 0x12]),
+            ("push de", vec![0xD5]), ("sub 0x12", vec![0xD6, 0x12]), ("ret c", vec![0xD8]),
+            ("reti", vec![0xD9]), ("jp c, 0x1234", vec![0xDA, 0x34, 0x12]),
+            ("call c, 0x1234", vec![0xDC, 0x34, 0x12]), ("sbc a, 0x12", vec![0xDE, 0x12]),
+            ("ldh (0xFF12), a", vec![0xE0, 0x12]), ("pop hl", vec![0xE1]), ("ld (c), a", vec![0xE2]),
+            ("push hl", vec![0xE5]), ("and 0x12", vec![0xE6, 0x12]), ("add sp, -2", vec![0xE8, 0xFE]),
+            ("jp hl", vec![0xE9]), ("ld (0x1234), a", vec![0xEA, 0x34, 0x12]),
+            ("xor 0x12", vec![0xEE, 0x12]), ("ldh a, (0xFF12)", vec![0xF0, 0x12]),
+            ("pop af", vec![0xF1]), ("ld a, (c)", vec![0xF2]), ("di", vec![0xF3]),
+            ("push af", vec![0xF5]), ("or 0x12", vec![0xF6, 0x12]), ("ld hl, sp+2", vec![0xF8, 0x02]),
+            ("ld sp, hl", vec![0xF9]), ("ld a, (0x1234)", vec![0xFA, 0x34, 0x12]), ("ei", vec![0xFB]),
+            ("cp 0x12", vec![0xFE, 0x12])
+        ].into_iter().map(|(s, b)| (s.to_string(), b)).collect();
+
+        for (i, rr) in R16.iter().enumerate() {
+            let i = i as u8;
+            v.push((format!("ld {}, 0x1234", rr), vec![0x01 | i << 4, 0x34, 0x12]));
+            v.push((format!("inc {}", rr), vec![0x03 | i << 4]));
+            v.push((format!("add hl, {}", rr), vec![0x09 | i << 4]));
+            v.push((format!("dec {}", rr), vec![0x0B | i << 4]));
+        }
+
+        for (i, r) in R8.iter().enumerate() {
+            let i = i as u8;
+            v.push((format!("inc {}", r), vec![0x04 | i << 3]));
+            v.push((format!("dec {}", r), vec![0x05 | i << 3]));
+            v.push((format!("ld {}, 0x12", r), vec![0x06 | i << 3, 0x12]));
+            v.push((format!("rst {}", i * 8), vec![0xC7 | i << 3]));
+        }
+
+        for (d, dst) in R8.iter().enumerate() {
+            for (s, src) in R8.iter().enumerate() {
+                if d != 6 || s != 6 {
+                    v.push((format!("ld {}, {}", dst, src), vec![0x40 | (d << 3 | s) as u8]));
+                }
+            }
+        }
+
+        let alu = ["add a,", "adc a,", "sub", "sbc a,", "and", "xor", "or", "cp"];
+        let cb = ["rlc", "rrc", "rl", "rr", "sla", "sra", "swap", "srl"];
+        for (n, r) in R8.iter().enumerate() {
+            for (o, op) in alu.iter().enumerate() {
+                v.push((format!("{} {}", op, r), vec![0x80 | (o << 3 | n) as u8]));
+            }
+            for (o, op) in cb.iter().enumerate() {
+                v.push((format!("{} {}", op, r), vec![0xCB, (o << 3 | n) as u8]));
+            }
+            for b in 0..8 {
+                v.push((format!("bit {}, {}", b, r), vec![0xCB, 0x40 | (b << 3 | n) as u8]));
+                v.push((format!("res {}, {}", b, r), vec![0xCB, 0x80 | (b << 3 | n) as u8]));
+                v.push((format!("set {}, {}", b, r), vec![0xCB, 0xC0 | (b << 3 | n) as u8]));
+            }
+        }
+        v
+    }
+
+    #[test]
+    fn conformance()
+    {
+        let mut base = HashSet::new();
+        let mut prefix = HashSet::new();
+
+        for (source, expect) in opcodes() {
+            let input = token::scan(&source).unwrap();
+            let (program, _) = parse::parse(HashSet::new(), input).unwrap();
+            let mut bytes: Vec<u8> = Vec::new();
+            write(&mut bytes, &program).unwrap();
+
+            assert_eq!(bytes, expect, "{}", source);
+            assert_eq!(program.location() as usize, bytes.len(), "{}", source);
+
+            if bytes[0] == 0xCB {
+                prefix.insert(bytes[1]);
+            } else {
+                base.insert(bytes[0]);
+            }
+        }
+
+        // All but the 0xCB prefix and the eleven unused opcodes
+        assert_eq!(base.len(), 256 - 12);
+        assert_eq!(prefix.len(), 256);
+    }
+
+    #[test]
+    fn forward_references()
+    {
+        let bytes = assemble(r#"
+                ld de, end
+                ld a, (end)
+                ld b, value
+                .byte value
+            end:
+            .set value, 0x2A
+        "#);
+        assert_eq!(bytes, [0x11, 0x09, 0x00, 0xFA, 0x09, 0x00, 0x06, 0x2A, 0x2A]);
+    }
+
+    #[test]
+    fn characters()
+    {
+        let bytes = assemble(r#"
+            cp 'A'
+            .asciz "a\"\n"
+            .byte 'z' - 'a', '\0'
+            .ascii "\x80\xFF"
+        "#);
+        assert_eq!(bytes, [0xFE, 0x41, 0x61, 0x22, 0x0A, 0x00, 0x19, 0x00, 0x80, 0xFF]);
+    }
+
+    #[test]
+    fn literals()
+    {
+        let bytes = assemble(r#"
+            ldh [$FF00 + $44], a
+            ld a, %1000_0001
+            and &17
+            cp 0FEh
+        "#);
+        assert_eq!(bytes, [0xE0, 0x44, 0x3E, 0x81, 0xE6, 0x0F, 0xFE, 0xFE]);
+    }
+
+    #[test]
+    fn data_words()
+    {
+        let bytes = assemble(r#"
+            table:
+                .word start, end, -1
+                .dw 0x1234
+                .farptr far, 0x012345
+            start:
+                .byte 0
+            .org 0x4000, 0
+            far:
+            end:
+        "#);
+        assert_eq!(bytes[..15], [
+            0x0E, 0x00, 0x00, 0x40, 0xFF, 0xFF,
+            0x34, 0x12,
+            0x00, 0x40, 0x01, 0x45, 0x23, 0x01,
+            0x00
+        ]);
+        assert_eq!(bytes.len(), 0x4000);
+    }
+
+    #[test]
+    fn sections()
+    {
+        let bytes = assemble(r#"
+            .data
+                .byte 0xAA
+            .text
+                nop
+                ld a, (tbl)
+            .data
+            tbl:
+                .byte 0xBB
+        "#);
+        assert_eq!(bytes, [0x00, 0xFA, 0x05, 0x00, 0xAA, 0xBB]);
+    }
+
+    #[test]
+    fn ram_sections()
+    {
+        let bytes = assemble(r#"
+            .bss
+            counter:
+                .ds 2
+            .text
+                ld a, (counter + 1)
+                .ds 2
+        "#);
+        assert_eq!(bytes, [0xFA, 0x01, 0xC0, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn placed_sections()
+    {
+        let bytes = assemble(r#"
+            .section "Vectors", ROM0[$08]
+            handler:
+                reti
+            .text
+                call handler
+        "#);
+        assert_eq!(bytes, [0xCD, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xD9]);
+
+        let bytes = assemble(r#"
+            .section "VBlank", ROM0[$40]
+                reti
+            .text
+            start:
+                .fill 0x80, 0
+                jp start
+        "#);
+        assert_eq!(bytes[0x40], 0xD9);
+        assert_eq!(bytes[0xC1..], [0xC3, 0x41, 0x00]);
+    }
+
+    #[test]
+    fn banked_sections()
+    {
+        let bytes = assemble(r#"
+            .section "Far", ROMX, bank[3]
+            far:
+                ret
+            .text
+                call far
+                .long far, far + 1, 2 + far - 1, far - far
+        "#);
+        assert_eq!(bytes[..15], [
+            0xCD, 0x00, 0x40, 0x00, 0x40, 0x03, 0x01, 0x40, 0x03,
+            0x01, 0x40, 0x03, 0x00, 0x00, 0x00
+        ]);
+        assert_eq!(bytes.len(), 0xC001);
+        assert_eq!(bytes[0xC000], 0xC9);
+    }
+
+    #[test]
+    fn directive_size()
+    {
+        let input = token::scan(r#"
+            .byte
+            .byte 1, 2
+            .ascii "ab"
+            .asciz "ab"
+            .fill 3, 0xFF
+        "#).unwrap();
+        let (program, _) = parse::parse(HashSet::new(), input).unwrap();
+        let mut bytes: Vec<u8> = Vec::new();
+        write(&mut bytes, &program).unwrap();
+        assert_eq!(bytes, [0x00, 0x01, 0x02, 0x61, 0x62, 0x61, 0x62, 0x00, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(program.location() as usize, bytes.len());
+    }
+
+    #[test]
+    fn invalid_operands()
+    {
+        let (mut program, _) = parse::parse(HashSet::new(), Vec::new()).unwrap();
+        program.sections[0].units.push(Instruction::Push(Operand::Register16(Register16::SP)).into());
+        let mut bytes: Vec<u8> = Vec::new();
+        assert!(write(&mut bytes, &program).is_err());
+    }
 }
@@ -3,6 +3,7 @@ extern crate gb_obj as obj;
 extern crate clap;
 
 mod token;
+mod expr;
 mod parse;
 mod gen;
 
@@ -18,7 +19,6 @@ use std::{
     collections::HashSet
 };
 use obj::{
-    Text,
     Section,
     Library
 };
@@ -48,6 +48,14 @@ fn read_file(name: &str) -> Result<String, std::io::Error>
     Ok(input)
 }
 
+fn read_file_binary(name: &str) -> Result<Vec<u8>, std::io::Error>
+{
+    let mut f = File::open(name)?;
+    let mut bytes = Vec::new();
+    f.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
 fn read_file_token(name: &str) -> Result<Vec<token::Token>, ()>
 {
     let mut input = match read_file(name) {
@@ -106,15 +114,28 @@ fn bin(output: &str, program: Program) -> Result<(), ()>
 
 fn lib(output: &str, program: Program, table: Table) -> Result<(), ()>
 {
-    let mut bin = Vec::new();
-    gen(output, &mut bin, program);
+    let mut sections = Vec::new();
+    for section in &program.sections {
+        let mut bin = Vec::new();
+        if let Err(e) = gen::write_section(&mut bin, section) {
+            eprintln!("error: {}: {}", output, e);
+            return Err(());
+        }
 
-    let mut text = Text::new(bin);
-    for (sym, addr) in table.iter() {
-        text.sym(sym, *addr);
+        let mut sect = Section::new(section.name.as_str(), section.start(), bin);
+        for (name, sym) in table.iter() {
+            if sym.section.as_ref() == Some(&section.name) {
+                sect.sym(name.as_str(), sym.value as u16);
+            }
+        }
+        sections.push(sect);
+    }
+    let mut lib = Library::new(sections);
+    for (name, sym) in table.iter() {
+        if sym.section.is_none() {
+            lib.sym(name.as_str(), sym.value);
+        }
     }
-    let sect = Section::new(text);
-    let lib = Library::new(sect);
     let bin = match lib.write() {
         Err(_) => return Err(()),
         Ok(bin) => bin
@@ -126,7 +147,26 @@ fn lib(output: &str, program: Program, table: Table) -> Result<(), ()>
     Ok(())
 }
 
-fn assemble(source: &str, output: &str, format: Format)
+/// Write a make rule listing the files the output depends on
+fn depend(file: &str, output: &str, source: &str, program: &Program) -> Result<(), ()>
+{
+    let mut rule = format!("{}: {}", output, source);
+    for path in program.dependencies() {
+        rule.push_str(&format!(" {}", path.display()));
+    }
+    rule.push('\n');
+
+    let mut out = create(file)?;
+    match out.write_all(rule.as_bytes()) {
+        Err(e) => {
+            eprintln!("error: {}: {}", file, e);
+            Err(())
+        },
+        Ok(_) => Ok(())
+    }
+}
+
+fn assemble(source: &str, output: &str, format: Format, deps: Option<&str>)
 {
     let mut input = match read_file(source) {
         Err(e) => {
@@ -137,7 +177,10 @@ fn assemble(source: &str, output: &str, format: Format)
     };
 
     let tokens = match token::scan(&input) {
-        Err(_) => return,
+        Err(e) => {
+            eprintln!("error: {}: {}", source, e);
+            return;
+        },
         Ok(tokens) => tokens
     };
 
@@ -150,10 +193,19 @@ fn assemble(source: &str, output: &str, format: Format)
     includes.insert(path);
 
     let (program, table) = match parse::parse(includes, tokens) {
-        Err(_) => return,
+        Err(e) => {
+            eprintln!("error: {}: {}", source, e);
+            return;
+        },
         Ok((p, t)) => (p, t)
     };
 
+    if let Some(file) = deps {
+        if depend(file, output, source, &program).is_err() {
+            return;
+        }
+    }
+
     match format {
         Format::Bin => {
             bin(output, program);
@@ -186,7 +238,11 @@ fn main()
                  .possible_values(&["bin", "lib"])
                  .takes_value(true)
                  .hide_possible_values(false)
-                 .help("Output in specified format"));
+                 .help("Output in specified format"))
+        .arg(Arg::with_name("deps")
+                 .short("M")
+                 .value_name("FILE")
+                 .help("Write a make rule listing the source dependencies"));
 
     let matches = app.get_matches();
 
@@ -197,5 +253,7 @@ fn main()
         _ => Format::Bin
     };
 
-    assemble(input, &output, format);
+    let deps = matches.value_of("deps");
+
+    assemble(input, output, format, deps);
 }
@@ -1,5 +1,6 @@
 
 use std::{
+    fmt,
     path::PathBuf,
     collections::{
         HashMap,
@@ -7,9 +8,16 @@ use std::{
     }
 };
 use crate::token;
+use crate::expr::{
+    self,
+    Expr,
+    Function,
+    Scope
+};
 use token::{
     Token,
-    Operation
+    Operation,
+    Operator
 };
 
 pub use token::{
@@ -35,35 +43,482 @@ impl Unit {
         use Unit::*;
         match self {
             Instruction(i) => i.bytes(),
-            Directive(d) => d.bytes(loc),
-            _ => 0,
+            Directive(d) => d.bytes(loc)
         }
     }
 }
 
+/// The part of the program assembled under a section
 #[derive(Clone, Debug, PartialEq)]
-pub struct Program {
+pub struct Extent {
+    pub start: u16,
+    pub size: u16
+}
+
+/// The memory a section is placed in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Region {
+    /// Cartridge ROM, banked past `$3FFF`
+    Rom,
+    /// Work RAM at `$C000-$DFFF`
+    Wram,
+    /// High RAM at `$FF80-$FFFE`
+    Hram,
+    /// Cartridge (save) RAM at `$A000-$BFFF`
+    Sram
+}
+
+impl Region {
+    fn start(&self) -> u16
+    {
+        use Region::*;
+        match self {
+            Rom => 0x0000,
+            Wram => 0xC000,
+            Hram => 0xFF80,
+            Sram => 0xA000
+        }
+    }
+
+    fn bounds(&self) -> (u32, u32)
+    {
+        let start = self.start() as u32;
+        (start, start + self.size())
+    }
+
+    fn size(&self) -> u32
+    {
+        use Region::*;
+        match self {
+            Rom => 0x8000,
+            Wram => 0x2000,
+            Hram => 0x7F,
+            Sram => 0x2000
+        }
+    }
+
+    /// The address and bank of a position in the region
+    fn locate(&self, position: u32) -> (u16, u16)
+    {
+        let size = BANK_SIZE as u32;
+        match self {
+            Region::Rom if position >= size => {
+                (BANK_SIZE + (position % size) as u16, (position / size) as u16)
+            },
+            _ => (position as u16, 0)
+        }
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        use Region::*;
+        let name = match self {
+            Rom => "ROM",
+            Wram => "WRAM",
+            Hram => "HRAM",
+            Sram => "SRAM"
+        };
+        let end = self.start() as u32 + self.size() - 1;
+        write!(f, "{} (${:04X}-${:04X})", name, self.start(), end)
+    }
+}
+
+
+/// A named part of the program with its own location counter
+#[derive(Clone, Debug, PartialEq)]
+pub struct Section {
+    pub name: String,
     pub units: Vec<Unit>,
-    location: u16
+    location: u16,
+    region: Region,
+    /// The position of the first byte, if fixed before placement
+    origin: Option<u32>,
+    /// The positions the section must be placed within
+    bounds: (u32, u32),
+    /// The modulus and remainder the first position must have
+    align: (u32, u32),
+    /// The position of the first byte once placed
+    start: u32
+}
+
+impl Section {
+    fn new(name: &str, region: Region) -> Self
+    {
+        Self {
+            name: name.into(),
+            units: Vec::new(),
+            location: 0,
+            region,
+            origin: None,
+            bounds: region.bounds(),
+            align: (1, 0),
+            start: 0
+        }
+    }
+
+    pub fn location(&self) -> u16
+    {
+        self.location
+    }
+
+    pub fn region(&self) -> Region
+    {
+        self.region
+    }
+
+    /// The offset into the ROM image, or the address in RAM
+    pub fn start(&self) -> u32
+    {
+        self.start
+    }
+
+    fn room(&self) -> u32
+    {
+        let mut size = self.bounds.1 - self.bounds.0;
+        if self.banked() {
+            size = size.min(BANK_SIZE as u32);
+        }
+        size.min(u16::MAX as u32).saturating_sub(self.location as u32)
+    }
+
+    /// Whether the section is kept within one switchable ROM bank
+    fn banked(&self) -> bool
+    {
+        self.region == Region::Rom && self.bounds.0 >= BANK_SIZE as u32
+    }
+}
+
+/// The section code is assembled under by default
+const TEXT: &str = "text";
+
+/// The first position from `start` with the given remainder
+fn align(start: u32, (modulus, remainder): (u32, u32)) -> u32
+{
+    let base = start - start % modulus + remainder;
+    if base < start { base + modulus } else { base }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program {
+    /// The sections, in the order they were declared
+    pub sections: Vec<Section>,
+    /// The index of the section units are assembled under
+    current: usize,
+    /// The files read while assembling the program
+    dependencies: Vec<PathBuf>
 }
 
 impl Program {
     fn new() -> Self
     {
         Self {
-            units: Vec::new(),
-            location: 0
+            sections: vec![Section::new(TEXT, Region::Rom)],
+            current: 0,
+            dependencies: Vec::new()
+        }
+    }
+
+    fn depend(&mut self, path: PathBuf)
+    {
+        if !self.dependencies.contains(&path) {
+            self.dependencies.push(path);
+        }
+    }
+
+    pub fn dependencies(&self) -> &[PathBuf]
+    {
+        &self.dependencies
+    }
+
+    fn resume(&mut self, name: &str) -> Option<&Section>
+    {
+        let index = self.sections.iter().position(|s| s.name == name)?;
+        self.current = index;
+        Some(&self.sections[index])
+    }
+
+    fn begin(&mut self, section: Section) -> &Section
+    {
+        self.sections.push(section);
+        self.current = self.sections.len() - 1;
+        &self.sections[self.current]
+    }
+
+    fn section(&mut self, name: &str, region: Region) -> &Section
+    {
+        if self.resume(name).is_none() {
+            self.begin(Section::new(name, region));
+        }
+        &self.sections[self.current]
+    }
+
+    fn current(&self) -> &Section
+    {
+        &self.sections[self.current]
+    }
+
+    fn push(&mut self, unit: Unit) -> Result<(), Error>
+    {
+        let section = &mut self.sections[self.current];
+        let bytes = unit.bytes(section.location);
+        section.location = match section.location.checked_add(bytes) {
+            None => {
+                let excess = section.location as u32 + bytes as u32 - u16::MAX as u32;
+                return Err(Error::RegionOverflow(section.region, excess));
+            },
+            Some(location) => location
+        };
+        section.units.push(unit);
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub fn location(&self) -> u16
+    {
+        self.sections.iter().map(|s| s.location).sum()
+    }
+
+    /// Place fixed sections, then the others in the first gap that fits
+    fn place(&mut self) -> Result<(), Error>
+    {
+        let fixed = (0..self.sections.len()).filter(|&i| self.sections[i].origin.is_some());
+        let free = (0..self.sections.len()).filter(|&i| self.sections[i].origin.is_none());
+        let order: Vec<usize> = fixed.chain(free).collect();
+
+        let mut placed: Vec<(Region, u32, u32)> = Vec::new();
+        let overlap = |placed: &[(Region, u32, u32)], region, start, end| {
+            placed.iter()
+                .find(|&&(r, s, e)| r == region && s < e && start < end && s < end && start < e)
+                .map(|&(_, _, e)| e)
+        };
+
+        for i in order {
+            let section = &self.sections[i];
+            let (region, size) = (section.region, section.location as u32);
+            let start = match section.origin {
+                Some(start) => {
+                    if start < section.bounds.0 || align(start, section.align) != start ||
+                       overlap(&placed, region, start, start + size).is_some() {
+                        return Err(Error::Placement(section.name.clone()));
+                    }
+                    start
+                },
+                None => {
+                    let bank = BANK_SIZE as u32;
+                    let mut start = align(section.bounds.0, section.align);
+                    while start + size <= section.bounds.1 {
+                        if section.banked() && size > 0 && start / bank != (start + size - 1) / bank {
+                            start = align(start - start % bank + bank, section.align);
+                        } else if let Some(end) = overlap(&placed, region, start, start + size) {
+                            start = align(end, section.align);
+                        } else {
+                            break;
+                        }
+                    }
+                    start
+                }
+            };
+
+            let end = start + size;
+            if end > section.bounds.1 {
+                if section.bounds != region.bounds() {
+                    return Err(Error::Placement(section.name.clone()));
+                }
+                return Err(Error::RegionOverflow(region, end - section.bounds.1));
+            }
+            placed.push((region, start, end));
+            self.sections[i].start = start;
+        }
+        Ok(())
+    }
+
+    fn origins(&self) -> HashMap<String, (Region, u32)>
+    {
+        self.sections.iter().map(|s| (s.name.clone(), (s.region, s.start))).collect()
+    }
+
+    fn extents(&self) -> HashMap<String, Extent>
+    {
+        self.sections.iter()
+            .map(|s| (s.name.clone(), Extent { start: s.region.locate(s.start).0, size: s.location }))
+            .collect()
+    }
+
+    /// Check that sections in RAM only reserve space
+    fn check(&self) -> Result<(), Error>
+    {
+        for section in &self.sections {
+            if section.region == Region::Rom {
+                continue;
+            }
+            let data = section.units.iter().any(|u| !matches!(u,
+                Unit::Directive(Directive::Space(_)) |
+                Unit::Directive(Directive::Org(_, _))
+            ));
+            if data {
+                return Err(Error::Reserved(section.name.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The size of a switchable ROM bank
+const BANK_SIZE: u16 = 0x4000;
+
+/// The number of ROM banks a cartridge may have
+const BANKS: u32 = 512;
+
+/// What a symbol stands for
+#[derive(Clone, Debug, PartialEq)]
+pub enum Kind {
+    /// A location in the program
+    Label,
+    /// A value fixed by `.equ`
+    Constant,
+    /// A value that may be changed by `.set`
+    Variable
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self {
+            Kind::Label => write!(f, "label"),
+            Kind::Constant => write!(f, "constant"),
+            Kind::Variable => write!(f, "variable")
+        }
+    }
+}
+
+/// A named value, along with where it was defined
+#[derive(Clone, Debug, PartialEq)]
+pub struct Symbol {
+    pub kind: Kind,
+    /// The value, or for a label its offset into the section
+    pub value: i32,
+    /// The section of a label, or `None` for a constant
+    pub section: Option<String>
+}
+
+impl Symbol {
+    fn label(program: &Program) -> Self
+    {
+        let section = program.current();
+        Self {
+            kind: Kind::Label,
+            value: section.location as i32,
+            section: Some(section.name.clone())
+        }
+    }
+
+    fn value(kind: Kind, value: i32) -> Self
+    {
+        Self {
+            kind,
+            value,
+            section: None
         }
     }
+}
 
-    fn push(&mut self, unit: Unit)
+pub type Table = HashMap<String, Symbol>;
+
+/// An error found while parsing the program
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// Unexpected or malformed input
+    Syntax,
+    /// A source file that could not be included
+    Include(String),
+    /// A part of a binary file outside of its size
+    IncludeRange(String, usize),
+    /// A relative jump whose target is too far away
+    JumpRange(String, i32),
+    /// A high page address outside of `$FF00-$FFFF`
+    HighRange(i32),
+    /// A restart address that is not one of the vectors
+    Vector(u16),
+    /// A value that does not fit in the operand
+    Overflow(i32, u8),
+    /// A signed offset outside of `-128..127`
+    OffsetRange(i32),
+    /// A size or position below zero
+    Negative(i32),
+    /// A symbol that was never defined
+    Undefined(String),
+    /// Division or remainder by zero
+    DivideByZero,
+    /// A symbol used where a label is expected
+    Label(String),
+    /// A section that does not exist
+    Section(String),
+    /// A local label used before any global label
+    Scope(String),
+    /// A symbol that cannot be defined again
+    Redefined(String, Kind),
+    /// A symbol or section not known until sections are placed
+    Unplaced(String),
+    /// Data in a section that may only reserve space
+    Reserved(String),
+    /// Sections that do not fit in their memory region
+    RegionOverflow(Region, u32),
+    /// A section that cannot be placed as declared
+    Placement(String),
+    /// Sections that move each time the program is parsed
+    Unsettled,
+    /// A label whose bank is lost in the expression it is used in
+    Bank(String)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
     {
-        self.location += unit.bytes(self.location);
-        self.units.push(unit);
+        use Error::*;
+        match self {
+            Syntax => write!(f, "syntax error"),
+            Include(name) => write!(f, "cannot include `{}`", name),
+            IncludeRange(name, size) => write!(f, "range outside of `{}` ({} bytes)", name, size),
+            JumpRange(target, offset) => {
+                write!(f, "relative jump to `{}` out of range ({}, expected -128..127)", target, offset)
+            },
+            HighRange(addr) => write!(f, "address {:#06x} outside of the high page $FF00-$FFFF", addr),
+            Vector(addr) => write!(f, "invalid restart vector {:#04x}", addr),
+            Overflow(value, bits) => write!(f, "value {} does not fit in {} bits", value, bits),
+            OffsetRange(offset) => write!(f, "offset {} out of range (expected -128..127)", offset),
+            Negative(value) => write!(f, "size or position {} cannot be negative", value),
+            Undefined(name) => write!(f, "undefined symbol `{}`", name),
+            DivideByZero => write!(f, "division by zero"),
+            Label(name) => write!(f, "`{}` is not a label", name),
+            Section(name) => write!(f, "unknown section `{}`", name),
+            Scope(name) => write!(f, "local label `{}` outside of a global label", name),
+            Redefined(name, kind) => write!(f, "`{}` is already defined as a {}", name, kind),
+            Unplaced(name) => write!(f, "`{}` is not known until sections are placed", name),
+            Reserved(name) => write!(f, "section `{}` can only reserve space", name),
+            RegionOverflow(region, size) => write!(f, "{} overflows by {} bytes", region, size),
+            Placement(name) => write!(f, "section `{}` cannot be placed as declared", name),
+            Unsettled => write!(f, "sections do not settle after {} passes", PASSES),
+            Bank(name) => write!(f, "the bank of `{}` is unknown in this expression", name)
+        }
     }
 }
 
-pub type Table = HashMap<String, u16>;
+impl From<expr::Error> for Error {
+    fn from(e: expr::Error) -> Self
+    {
+        match e {
+            expr::Error::Undefined(name) => Error::Undefined(name),
+            expr::Error::DivideByZero => Error::DivideByZero,
+            expr::Error::Operator(_) |
+            expr::Error::Argument(_) => Error::Syntax,
+            expr::Error::Label(name) => Error::Label(name),
+            expr::Error::Section(name) => Error::Section(name),
+            expr::Error::Unplaced(name) => Error::Unplaced(name)
+        }
+    }
+}
 
 /// The Operands are either an 8-bit (or 16-bit)
 /// immediate value or a register.
@@ -76,7 +531,22 @@ pub enum Operand {
     Indirect(Register),
     Indirect16(Register16),
     Flag(FlagRegister),
-    Symbol(String),
+    /// A value that is yet to be evaluated
+    Expression(Expr),
+    /// An absolute memory address `(a16)`
+    Address(u16),
+    /// An address in the high page `($FF00+a8)`
+    High(u8),
+    /// The address in `hl`, incremented after use `(hl+)`
+    IndirectIncrement,
+    /// The address in `hl`, decremented after use `(hl-)`
+    IndirectDecrement,
+    /// A signed 8-bit offset
+    Offset(i8),
+    /// A 16-bit address within a ROM bank
+    Far(u8, u16),
+    /// A value resolved once the whole program is parsed
+    Deferred(Width, Expr)
 }
 
 impl Bytes for Operand {
@@ -84,16 +554,48 @@ impl Bytes for Operand {
     {
         use Operand::*;
         match self {
-            Immediate8(_) => 1,
-            Immediate16(_) | Symbol(_) => 2,
+            Immediate8(_) | High(_) | Offset(_) => 1,
+            Immediate16(_) | Expression(_) | Address(_) => 2,
+            Far(_, _) => 3,
+            Deferred(width, _) => width.bytes(),
             _ => 0,
         }
     }
 }
 
+/// The kind of operand a deferred value becomes
+#[derive(Clone, Debug, PartialEq)]
+pub enum Width {
+    /// An 8-bit immediate
+    Byte,
+    /// A 16-bit immediate
+    Word,
+    /// An absolute memory address `(a16)`
+    Address,
+    /// An address in the high page `($FF00+a8)`
+    High,
+    /// A signed 8-bit offset
+    Offset,
+    /// A banked address
+    Far
+}
+
+impl Bytes for Width {
+    fn bytes(&self) -> u16
+    {
+        use Width::*;
+        match self {
+            Byte | High | Offset => 1,
+            Word | Address => 2,
+            Far => 3
+        }
+    }
+}
+
 /// All machine instructions.
 #[derive(Clone, Debug, PartialEq)]
 pub enum Instruction {
+    Adc(Operand, Operand),
     Add(Operand, Operand),
     And(Operand),
     Bit(Operand, Operand),
@@ -122,7 +624,7 @@ pub enum Instruction {
     Reti,
     Res(Operand, Operand),
     Rl(Operand),
-    Rla(Operand),
+    Rla,
     Rlc(Operand),
     Rlca,
     Rr(Operand),
@@ -154,16 +656,19 @@ impl Bytes for Instruction {
     {
         use Instruction::*;
         match self {
-            Add(a, b) | Bit(a, b) | Call_2(a, b) |
-            Jp_2(a, b) | Jr_2(a, b) | Ld(a, b) |
-            Res(a, b) | Sbc(a, b) | Set(a, b) => 1 + a.bytes() + b.bytes(),
+            Bit(_, _) | Res(_, _) | Set(_, _) |
+            Rl(_) | Rlc(_) | Rr(_) | Rrc(_) |
+            Sla(_) | Sra(_) | Srl(_) | Swap(_) => 2,
+            Jr_1(_) | Jr_2(_, _) => 2,
+            Adc(a, b) | Add(a, b) | Call_2(a, b) |
+            Jp_2(a, b) | Ld(a, b) |
+            Sbc(a, b) => 1 + a.bytes() + b.bytes(),
             And(a) | Call_1(a) | Cp(a) |
-            Dec(a) | Inc(a) | Jp_1(a) | Jr_1(a) |
+            Dec(a) | Inc(a) | Jp_1(a) |
             Or(a) | Pop(a) | Push(a) |
-            Ret_1(a) | Rl(a) | Rla(a) |
-            Rr(a) | Rrc(a) | Rst(a) |
-            Sla(a) | Sra(a) | Srl(a) |
-            Sub(a) | Swap(a) | Xor(a) => 1 + a.bytes(),
+            Ret_1(a) |
+            Sub(a) | Xor(a) => 1 + a.bytes(),
+            Rst(_) => 1,
             Stop => 2,
             _ => 1,
         }
@@ -174,10 +679,14 @@ impl Bytes for Instruction {
 pub enum Directive {
     Ascii(Vec<u8>),
     Asciz(Vec<u8>),
-    Byte(Option<Vec<u8>>),
+    Byte(Option<Vec<Operand>>),
     Fill(usize, u8),
+    Incbin(Vec<u8>),
+    Long(Vec<Operand>),
     Org(usize, u8),
-    Utf8(Vec<u8>)
+    Space(usize),
+    Utf8(Vec<u8>),
+    Word(Vec<Operand>)
 }
 
 impl From<Directive> for Unit {
@@ -192,12 +701,16 @@ impl Directive {
     {
         use Directive::*;
         match self {
-            Ascii(v) | Asciz(v) | Utf8(v) => v.len() as u16,
+            Ascii(v) | Utf8(v) | Incbin(v) => v.len() as u16,
+            Asciz(v) => v.len() as u16 + 1,
             Byte(o) => match o {
-                None => 0,
+                None => 1,
                 Some(v) => v.len() as u16
             },
-            Fill(size, _) | Org(size, _) => *size as u16
+            Fill(size, _) | Org(size, _) => *size as u16,
+            Space(size) => *size as u16,
+            Word(v) => v.len() as u16 * 2,
+            Long(v) => v.len() as u16 * 3
         }
     }
 }
@@ -205,16 +718,34 @@ impl Directive {
 struct Parser {
     pos: usize,
     tokens: Vec<Token>,
-    symbols: Table
+    symbols: Table,
+    /// The global label that local labels belong to
+    scope: Option<String>,
+    /// The section and offset of the anonymous labels defined so far
+    anonymous: Vec<(String, u16)>,
+    /// The section of the unit being parsed
+    section: String,
+    /// The offset of the unit being parsed into its section
+    location: u16,
+    /// Where the previous pass placed each section
+    origins: HashMap<String, (Region, u32)>,
+    /// The section extents, once placed
+    sections: Option<HashMap<String, Extent>>
 }
 
 impl Parser {
-    fn new(tokens: Vec<Token>) -> Self
+    fn new(tokens: Vec<Token>, origins: HashMap<String, (Region, u32)>) -> Self
     {
         Self {
             pos: 0,
             tokens,
-            symbols: HashMap::new()
+            symbols: HashMap::new(),
+            scope: None,
+            anonymous: Vec::new(),
+            section: TEXT.into(),
+            location: 0,
+            origins,
+            sections: None
         }
     }
 
@@ -239,6 +770,94 @@ impl Parser {
         }
     }
 
+    fn eval(&self, e: &Expr) -> Result<i32, Error>
+    {
+        Ok(e.eval(self)?)
+    }
+
+    /// The full name of a symbol, i.e. `main.loop` for `.loop`
+    fn qualify(&self, name: String) -> Result<String, Error>
+    {
+        if !name.starts_with('.') {
+            return Ok(name);
+        }
+        match &self.scope {
+            None => Err(Error::Scope(name)),
+            Some(scope) => Ok(format!("{}{}", scope, name))
+        }
+    }
+
+    /// Define a symbol, where only a variable may be assigned again
+    fn define(&mut self, name: String, symbol: Symbol) -> Result<(), Error>
+    {
+        match self.symbols.get(&name) {
+            Some(old) if old.kind != Kind::Variable || symbol.kind != Kind::Variable => {
+                Err(Error::Redefined(name, old.kind.clone()))
+            },
+            _ => {
+                self.symbols.insert(name, symbol);
+                Ok(())
+            }
+        }
+    }
+
+    /// Replace each variable with its value so far
+    fn freeze(&self, e: Expr) -> Expr
+    {
+        match e {
+            Expr::Symbol(name) => match self.symbols.get(&name) {
+                Some(symbol) if symbol.kind == Kind::Variable => Expr::Value(symbol.value),
+                _ => Expr::Symbol(name)
+            },
+            Expr::Unary(op, a) => Expr::Unary(op, Box::new(self.freeze(*a))),
+            Expr::Binary(op, a, b) => {
+                Expr::Binary(op, Box::new(self.freeze(*a)), Box::new(self.freeze(*b)))
+            },
+            e => e
+        }
+    }
+
+    fn symbol(&self, name: &str) -> Result<&Symbol, expr::Error>
+    {
+        match self.symbols.get(name) {
+            None => Err(expr::Error::Undefined(name.into())),
+            Some(symbol) => Ok(symbol)
+        }
+    }
+
+    fn section(&self, name: &str) -> Result<&Extent, expr::Error>
+    {
+        match &self.sections {
+            None => Err(expr::Error::Unplaced(name.into())),
+            Some(sections) => match sections.get(name) {
+                None => Err(expr::Error::Section(name.into())),
+                Some(extent) => Ok(extent)
+            }
+        }
+    }
+
+    fn origin(&self, section: &str) -> Result<i32, expr::Error>
+    {
+        match self.origins.get(section) {
+            Some((region, start)) => Ok(region.locate(*start).0 as i32),
+            None => Ok(self.section(section)?.start as i32)
+        }
+    }
+
+    /// Switch sections, guessing where one new to this pass starts
+    fn enter(&mut self, section: &Section)
+    {
+        self.section = section.name.clone();
+        let name = section.name.clone();
+        match section.origin {
+            Some(start) => { self.origins.insert(name, (section.region, start)); },
+            None => {
+                let start = align(section.bounds.0, section.align);
+                self.origins.entry(name).or_insert((section.region, start));
+            }
+        }
+    }
+
     fn include(&mut self, mut tokens: Vec<Token>, rewind: usize)
     {
         let mut end = self.tokens.split_off(self.pos + 1);
@@ -249,131 +868,575 @@ impl Parser {
     }
 }
 
-fn id(parser: &mut Parser) -> Result<String, ()>
+impl Scope for Parser {
+    fn value(&self, name: &str) -> Result<i32, expr::Error>
+    {
+        if let Some((reference, index)) = name.split_once('@') {
+            return match index.parse().ok().and_then(|i: usize| self.anonymous.get(i)) {
+                None => Err(expr::Error::Undefined(reference.into())),
+                Some((section, loc)) => Ok(self.origin(section)? + *loc as i32)
+            };
+        }
+        let symbol = self.symbol(name)?;
+        match &symbol.section {
+            None => Ok(symbol.value),
+            Some(section) => match self.origin(section) {
+                Err(expr::Error::Unplaced(_)) => Err(expr::Error::Unplaced(name.into())),
+                origin => Ok(origin? + symbol.value)
+            }
+        }
+    }
+
+    fn bank(&self, name: &str) -> Result<i32, expr::Error>
+    {
+        let symbol = self.symbol(name)?;
+        let section = match (&symbol.kind, &symbol.section) {
+            (Kind::Label, Some(section)) => section,
+            _ => return Err(expr::Error::Label(name.into()))
+        };
+        match self.origins.get(section) {
+            None => Err(expr::Error::Unplaced(name.into())),
+            Some((region, start)) => Ok(region.locate(start + symbol.value as u32).1 as i32)
+        }
+    }
+
+    fn size(&self, section: &str) -> Result<i32, expr::Error>
+    {
+        Ok(self.section(section)?.size as i32)
+    }
+
+    fn start(&self, section: &str) -> Result<i32, expr::Error>
+    {
+        self.origin(section)
+    }
+}
+
+fn id(parser: &mut Parser) -> Result<String, Error>
 {
     parser.next();
     match parser.look() {
         Some(Token::Id(s)) => Ok(s.clone()),
-        _ => return Err(())
+        _ => Err(Error::Syntax)
     }
 }
 
 /// A newline following an instruction
-fn newline(parser: &mut Parser) -> Result<(), ()>
+fn newline(parser: &mut Parser) -> Result<(), Error>
 {
     parser.next();
     match parser.look() {
         None | Some(Token::Newline) => (),
-        _ => return Err(())
+        _ => return Err(Error::Syntax)
     }
     Ok(())
 }
 
-fn byte(parser: &mut Parser) -> Result<u8, ()>
+/// Check that a value fits in `bits`, unsigned or in two's complement
+fn fit(v: i32, bits: u8) -> Result<i32, Error>
 {
-    match parser.ahead() {
-        Some(Token::Value(v)) => {
-            if v > std::u8::MAX as u16 {
-                return Err(())
-            }
-            parser.next();
-            Ok(v as u8)
-        },
-        _ => Err(())
+    let min = -(1 << (bits - 1));
+    let max = (1 << bits) - 1;
+    if v < min || v > max {
+        return Err(Error::Overflow(v, bits));
     }
+    Ok(v)
 }
 
-fn word(parser: &mut Parser) -> Result<Operand, ()>
+fn eval8(parser: &Parser, e: &Expr) -> Result<u8, Error>
 {
-    Ok(match parser.ahead() {
-        Some(Token::Value(v)) => {
-            parser.next();
-            Operand::Immediate16(v)
-        },
-        Some(Token::Id(s)) => {
-            parser.next();
-            Operand::Symbol(s.into())
-        },
-        _ => return Err(())
-    })
+    Ok(fit(parser.eval(e)?, 8)? as u8)
 }
 
-fn ascii(parser: &mut Parser) -> Result<Vec<u8>, ()>
+fn eval16(parser: &Parser, e: &Expr) -> Result<u16, Error>
 {
-    match parser.ahead() {
-        Some(Token::String(s)) => {
-            if !s.is_ascii() {
-                return Err(());
-            }
-            parser.next();
-            Ok(s.bytes().collect())
-        },
-        _ => Err(())
-    }
+    Ok(fit(parser.eval(e)?, 16)? as u16)
 }
 
-fn utf8(parser: &mut Parser) -> Result<Vec<u8>, ()>
+/// The binding strength of a binary operator
+fn precedence(op: &Operator) -> Option<u8>
 {
-    match parser.ahead() {
-        Some(Token::String(s)) => {
-            parser.next();
-            Ok(s.bytes().collect())
-        },
-        _ => Err(())
-    }
+    use Operator::*;
+    Some(match op {
+        DoublePipe => 1,
+        DoubleAmpersand => 2,
+        Pipe => 3,
+        Caret => 4,
+        Ampersand => 5,
+        Equal | NotEqual => 6,
+        Less | LessEqual | Greater | GreaterEqual => 7,
+        ShiftLeft | ShiftRight => 8,
+        Plus | Minus => 9,
+        Star | Slash | Percent => 10,
+        Tilde | Bang => return None
+    })
 }
 
-#[inline]
-fn comma(parser: &mut Parser) -> Result<(), ()>
+fn primary(parser: &mut Parser) -> Result<Expr, Error>
 {
     match parser.ahead() {
-        Some(Token::Comma) => {
+        Some(Token::Value(v)) => {
             parser.next();
-            Ok(())
+            Ok(Expr::Value(v))
         },
-        _ => return Err(())
-    }
-}
-
-fn reg16_not_sp_pc(parser: &mut Parser) -> Result<Operand, ()>
-{
-    match parser.ahead() {
-        Some(Token::Register16(Register16::SP)) |
-        Some(Token::Register16(Register16::PC)) => {
-            Err(())
+        Some(Token::Location) => {
+            parser.next();
+            Ok(address(&parser.section, parser.location))
         },
-        Some(Token::Register16(r)) => {
+        Some(Token::Anonymous(n)) => {
+            parser.next();
+            anonymous(parser, n)
+        },
+        Some(Token::Id(ref s)) if parser.tokens.get(parser.pos + 2) == Some(&Token::LeftParen) => {
+            let f = match Function::named(s) {
+                None => return Err(Error::Syntax),
+                Some(f) => f
+            };
+            parser.next();
+            let e = primary(parser)?;
+            Ok(Expr::Call(f, Box::new(e)))
+        },
+        Some(Token::Id(s)) => {
+            parser.next();
+            Ok(Expr::Symbol(parser.qualify(s)?))
+        },
+        Some(Token::Operator(op)) => {
+            use Operator::*;
+            match op {
+                Plus | Minus | Tilde | Bang => {
+                    parser.next();
+                    Ok(Expr::Unary(op, Box::new(primary(parser)?)))
+                },
+                _ => Err(Error::Syntax)
+            }
+        },
+        Some(Token::LeftParen) => {
+            parser.next();
+            let e = binary(parser, 1)?;
+            match parser.ahead() {
+                Some(Token::RightParen) => {
+                    parser.next();
+                    Ok(e)
+                },
+                _ => Err(Error::Syntax)
+            }
+        },
+        _ => Err(Error::Syntax)
+    }
+}
+
+/// The `n`th anonymous label after (or before) the current location
+fn anonymous(parser: &Parser, n: i32) -> Result<Expr, Error>
+{
+    let sign = if n > 0 { "+" } else { "-" };
+    let reference = format!(":{}", sign.repeat(n.unsigned_abs() as usize));
+    let defined = parser.anonymous.len() as i32;
+    if n > 0 {
+        return Ok(Expr::Symbol(format!("{}@{}", reference, defined + n - 1)));
+    }
+    let index = defined + n;
+    if index < 0 {
+        return Err(Error::Undefined(reference));
+    }
+    let (section, loc) = &parser.anonymous[index as usize];
+    Ok(address(section, *loc))
+}
+
+/// The address at an offset into a section
+fn address(section: &str, offset: u16) -> Expr
+{
+    Expr::Binary(Operator::Plus,
+        Box::new(Expr::Call(Function::Startof, Box::new(Expr::Symbol(section.into())))),
+        Box::new(Expr::Value(offset as i32))
+    )
+}
+
+/// A chain of binary operators binding at least as tightly as `min`
+fn binary(parser: &mut Parser, min: u8) -> Result<Expr, Error>
+{
+    let mut lhs = primary(parser)?;
+    while let Some(Token::Operator(op)) = parser.ahead() {
+        let prec = match precedence(&op) {
+            Some(prec) if prec >= min => prec,
+            _ => break
+        };
+        parser.next();
+        let rhs = binary(parser, prec + 1)?;
+        lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+    }
+    Ok(lhs)
+}
+
+/// A constant expression, i.e. `SCRN0 + 32*row + col`
+fn expression(parser: &mut Parser) -> Result<Expr, Error>
+{
+    let e = binary(parser, 1)?;
+    Ok(parser.freeze(e))
+}
+
+fn byte(parser: &mut Parser) -> Result<u8, Error>
+{
+    let e = expression(parser)?;
+    eval8(parser, &e)
+}
+
+/// Evaluate an expression as an operand of the given width
+fn narrow(parser: &Parser, width: &Width, e: &Expr) -> Result<Operand, Error>
+{
+    Ok(match width {
+        Width::Byte => Operand::Immediate8(eval8(parser, e)?),
+        Width::Word => Operand::Immediate16(eval16(parser, e)?),
+        Width::Address => Operand::Address(eval16(parser, e)?),
+        Width::High => {
+            let addr = parser.eval(e)?;
+            if !(0xFF00..=0xFFFF).contains(&addr) {
+                return Err(Error::HighRange(addr));
+            }
+            Operand::High(addr as u8)
+        },
+        Width::Offset => {
+            let offset = parser.eval(e)?;
+            if offset < i8::MIN as i32 || offset > i8::MAX as i32 {
+                return Err(Error::OffsetRange(offset));
+            }
+            Operand::Offset(offset as i8)
+        },
+        Width::Far => {
+            let v = parser.eval(e)?;
+            let bank = match label(parser, e)? {
+                Some(name) => Some(parser.bank(&name)?),
+                None => None
+            };
+            match bank {
+                Some(bank) => Operand::Far(bank as u8, v as u16),
+                None => {
+                    let v = fit(v, 24)?;
+                    Operand::Far((v >> 16) as u8, v as u16)
+                }
+            }
+        }
+    })
+}
+
+/// The label an address is an offset from, if any
+fn label(parser: &Parser, e: &Expr) -> Result<Option<String>, Error>
+{
+    Ok(match e {
+        Expr::Symbol(name) if parser.symbol(name)?.kind == Kind::Label => Some(name.clone()),
+        Expr::Value(_) | Expr::Symbol(_) | Expr::Call(_, _) => None,
+        Expr::Binary(op, a, b) => match (op, label(parser, a)?, label(parser, b)?) {
+            (_, None, None) => None,
+            (Operator::Plus, Some(name), None) |
+            (Operator::Plus, None, Some(name)) |
+            (Operator::Minus, Some(name), None) => Some(name),
+            (Operator::Minus, Some(_), Some(_)) => None,
+            (_, Some(name), _) | (_, _, Some(name)) => return Err(Error::Bank(name))
+        },
+        Expr::Unary(_, a) => match label(parser, a)? {
+            Some(name) => return Err(Error::Bank(name)),
+            None => None
+        }
+    })
+}
+
+/// Narrow an expression, or leave it to the second pass
+fn defer(parser: &Parser, width: Width, e: Expr) -> Result<Operand, Error>
+{
+    match narrow(parser, &width, &e) {
+        Err(Error::Undefined(_)) |
+        Err(Error::Unplaced(_)) => Ok(Operand::Deferred(width, e)),
+        result => result
+    }
+}
+
+fn word(parser: &mut Parser) -> Result<Operand, Error>
+{
+    let e = expression(parser)?;
+    defer(parser, Width::Word, e)
+}
+
+fn ascii(parser: &mut Parser) -> Result<Vec<u8>, Error>
+{
+    match parser.ahead() {
+        Some(Token::String(s, ascii)) => {
+            if !ascii {
+                return Err(Error::Syntax);
+            }
+            parser.next();
+            Ok(s)
+        },
+        _ => Err(Error::Syntax)
+    }
+}
+
+fn utf8(parser: &mut Parser) -> Result<Vec<u8>, Error>
+{
+    match parser.ahead() {
+        Some(Token::String(s, _)) => {
+            parser.next();
+            Ok(s)
+        },
+        _ => Err(Error::Syntax)
+    }
+}
+
+#[inline]
+fn comma(parser: &mut Parser) -> Result<(), Error>
+{
+    match parser.ahead() {
+        Some(Token::Comma) => {
+            parser.next();
+            Ok(())
+        },
+        _ => Err(Error::Syntax)
+    }
+}
+
+fn reg16_not_sp_pc(parser: &mut Parser) -> Result<Operand, Error>
+{
+    match parser.ahead() {
+        Some(Token::Register16(Register16::SP)) |
+        Some(Token::Register16(Register16::PC)) => {
+            Err(Error::Syntax)
+        },
+        Some(Token::Register16(r)) => {
             parser.next();
             Ok(Operand::Register16(r))
         },
-        _ => Err(())
+        _ => Err(Error::Syntax)
     }
 }
 
-fn reg_any_reg16_hl(parser: &mut Parser) -> Result<Operand, ()>
+fn reg_any_reg16_hl(parser: &mut Parser) -> Result<Operand, Error>
 {
     match parser.ahead() {
         Some(Token::Register(r)) => {
             parser.next();
             Ok(Operand::Register(r))
         },
+        Some(Token::LeftParen) | Some(Token::LeftBracket) => {
+            match indirect(parser)? {
+                Operand::Indirect16(Register16::HL) => Ok(Operand::Indirect16(Register16::HL)),
+                _ => Err(Error::Syntax)
+            }
+        },
+        _ => Err(Error::Syntax)
+    }
+}
+
+/// The offset into the high page of `($FF00+n)`
+fn high_offset(parser: &mut Parser) -> Result<Operand, Error>
+{
+    if let Some(Token::Register(Register::C)) = parser.ahead() {
+        parser.next();
+        return Ok(Operand::Indirect(Register::C));
+    }
+
+    let e = expression(parser)?;
+    let addr = Expr::Binary(Operator::Plus, Box::new(Expr::Value(0xFF00)), Box::new(e));
+    defer(parser, Width::High, addr)
+}
+
+/// An indirect operand enclosed in parentheses or brackets
+fn indirect(parser: &mut Parser) -> Result<Operand, Error>
+{
+    let close = match parser.ahead() {
+        Some(Token::LeftParen) => Token::RightParen,
+        Some(Token::LeftBracket) => Token::RightBracket,
+        _ => return Err(Error::Syntax)
+    };
+    parser.next();
+
+    let operand = match parser.ahead() {
+        Some(Token::Register(Register::C)) => {
+            parser.next();
+            Operand::Indirect(Register::C)
+        },
+        Some(Token::Register16(Register16::AF)) |
+        Some(Token::Register16(Register16::SP)) |
+        Some(Token::Register16(Register16::PC)) => {
+            return Err(Error::Syntax);
+        },
         Some(Token::Register16(Register16::HL)) => {
             parser.next();
-            Ok(Operand::Indirect16(Register16::HL))
+            match parser.ahead() {
+                Some(Token::Operator(Operator::Plus)) => {
+                    parser.next();
+                    Operand::IndirectIncrement
+                },
+                Some(Token::Operator(Operator::Minus)) => {
+                    parser.next();
+                    Operand::IndirectDecrement
+                },
+                _ => Operand::Indirect16(Register16::HL)
+            }
+        },
+        Some(Token::Register16(r)) => {
+            parser.next();
+            Operand::Indirect16(r)
+        },
+        Some(Token::Id(ref s)) if s == "hli" => {
+            parser.next();
+            Operand::IndirectIncrement
+        },
+        Some(Token::Id(ref s)) if s == "hld" => {
+            parser.next();
+            Operand::IndirectDecrement
+        },
+        Some(Token::Value(0xFF00)) if parser.tokens.get(parser.pos + 2) == Some(&Token::Operator(Operator::Plus)) => {
+            parser.next();
+            parser.next();
+            high_offset(parser)?
+        },
+        _ => Operand::Deferred(Width::Address, expression(parser)?)
+    };
+
+    match parser.ahead() {
+        Some(ref t) if *t == close => {
+            parser.next();
+            Ok(operand)
         },
-        _ => return Err(())
+        _ => Err(Error::Syntax)
     }
 }
 
-fn value_byte(parser: &mut Parser) -> Result<(usize, u8), ()>
+/// Any single operand of an instruction
+fn operand(parser: &mut Parser) -> Result<Operand, Error>
 {
-    let size = match parser.ahead() {
-        Some(Token::Value(v)) => {
+    Ok(match parser.ahead() {
+        Some(Token::Register(r)) => {
             parser.next();
-            v as usize
+            Operand::Register(r)
         },
-        _ => return Err(())
-    };
+        Some(Token::Register16(r)) => {
+            parser.next();
+            Operand::Register16(r)
+        },
+        Some(Token::LeftBracket) => indirect(parser)?,
+        Some(Token::LeftParen) if enclosed(parser) => indirect(parser)?,
+        _ => Operand::Expression(expression(parser)?)
+    })
+}
+
+/// Whether the parenthesis ahead encloses the whole operand
+fn enclosed(parser: &Parser) -> bool
+{
+    let mut depth = 0;
+    for (i, token) in parser.tokens.iter().enumerate().skip(parser.pos + 1) {
+        match token {
+            Token::LeftParen => depth += 1,
+            Token::RightParen => {
+                depth -= 1;
+                if depth == 0 {
+                    return matches!(parser.tokens.get(i + 1),
+                        None | Some(Token::Newline) | Some(Token::Comma));
+                }
+            },
+            Token::Newline => break,
+            _ => ()
+        }
+    }
+    false
+}
+
+fn imm8(parser: &mut Parser, operand: Operand) -> Result<Operand, Error>
+{
+    match operand {
+        Operand::Expression(e) => defer(parser, Width::Byte, e),
+        _ => Err(Error::Syntax)
+    }
+}
+
+fn imm16(parser: &mut Parser, operand: Operand) -> Result<Operand, Error>
+{
+    match operand {
+        Operand::Expression(e) => defer(parser, Width::Word, e),
+        _ => Err(Error::Syntax)
+    }
+}
+
+/// The source operand of an 8-bit ALU instruction
+fn arith(parser: &mut Parser) -> Result<Operand, Error>
+{
+    let mut src = operand(parser)?;
+    if src == Operand::Register(Register::A) && comma(parser).is_ok() {
+        src = operand(parser)?;
+    }
+
+    match src {
+        Operand::Register(_) | Operand::Indirect16(Register16::HL) => Ok(src),
+        Operand::Expression(_) => imm8(parser, src),
+        _ => Err(Error::Syntax)
+    }
+}
+
+fn inc_dec(parser: &mut Parser) -> Result<Operand, Error>
+{
+    let operand = operand(parser)?;
+    match operand {
+        Operand::Register(_) | Operand::Indirect16(Register16::HL) => Ok(operand),
+        Operand::Register16(Register16::AF) |
+        Operand::Register16(Register16::PC) => Err(Error::Syntax),
+        Operand::Register16(_) => Ok(operand),
+        _ => Err(Error::Syntax)
+    }
+}
+
+/// A signed 8-bit offset, i.e. the `-2` of `add sp, -2`
+fn offset(parser: &mut Parser) -> Result<Operand, Error>
+{
+    let e = expression(parser)?;
+    defer(parser, Width::Offset, e)
+}
+
+fn bit_index(parser: &mut Parser) -> Result<Operand, Error>
+{
+    let e = expression(parser)?;
+    match eval8(parser, &e)? {
+        n if n <= 7 => Ok(Operand::Immediate8(n)),
+        _ => Err(Error::Syntax)
+    }
+}
+
+/// The operands of a bit instruction, i.e. `bit 3, (hl)`
+fn bit(parser: &mut Parser) -> Result<(Operand, Operand), Error>
+{
+    let index = bit_index(parser)?;
+    comma(parser)?;
+    let target = reg_any_reg16_hl(parser)?;
+    Ok((index, target))
+}
+
+/// The comma separated values of a data directive
+fn data(parser: &mut Parser, width: Width) -> Result<Vec<Operand>, Error>
+{
+    let mut values = Vec::new();
+    loop {
+        let e = expression(parser)?;
+        values.push(defer(parser, width.clone(), e)?);
+        if comma(parser).is_err() {
+            break;
+        }
+    }
+    Ok(values)
+}
+
+/// A size or position, which cannot be negative
+fn count(parser: &mut Parser) -> Result<usize, Error>
+{
+    let e = expression(parser)?;
+    let size = parser.eval(&e)?;
+    if size < 0 {
+        return Err(Error::Negative(size));
+    }
+    if size > u16::MAX as i32 {
+        return Err(Error::Overflow(size, 16));
+    }
+    Ok(size as usize)
+}
+
+fn value_byte(parser: &mut Parser) -> Result<(usize, u8), Error>
+{
+    let size = count(parser)?;
 
     comma(parser)?;
 
@@ -381,115 +1444,552 @@ fn value_byte(parser: &mut Parser) -> Result<(usize, u8), ()>
     Ok((size, byte))
 }
 
-fn call(parser: &mut Parser) -> Result<Instruction, ()>
+/// The contents of a binary file, i.e. `.incbin "file", 2, 3`
+fn incbin(parser: &mut Parser, program: &mut Program) -> Result<Vec<u8>, Error>
+{
+    let name = match String::from_utf8(utf8(parser)?) {
+        Err(_) => return Err(Error::Syntax),
+        Ok(s) => s
+    };
+
+    let path: PathBuf = match crate::path(&name) {
+        Err(_) => return Err(Error::Include(name)),
+        Ok(path) => path
+    };
+
+    let bytes = match crate::read_file_binary(&name) {
+        Err(_) => return Err(Error::Include(name)),
+        Ok(bytes) => bytes
+    };
+
+    let offset = match comma(parser) {
+        Err(_) => 0,
+        Ok(_) => count(parser)?
+    };
+    let length = match comma(parser) {
+        Err(_) => bytes.len().saturating_sub(offset),
+        Ok(_) => count(parser)?
+    };
+
+    if offset + length > bytes.len() {
+        return Err(Error::IncludeRange(name, bytes.len()));
+    }
+    program.depend(path);
+    Ok(bytes[offset..offset + length].to_vec())
+}
+
+/// A value in square brackets, i.e. `bank[3]`
+fn bracket(parser: &mut Parser) -> Result<usize, Error>
+{
+    match parser.ahead() {
+        Some(Token::LeftBracket) => parser.next(),
+        _ => return Err(Error::Syntax)
+    }
+    let v = count(parser)?;
+    match parser.ahead() {
+        Some(Token::RightBracket) => {
+            parser.next();
+            Ok(v)
+        },
+        _ => Err(Error::Syntax)
+    }
+}
+
+/// `.section "Name", ROMX[$4000], bank[1], align[8]`, or just a name to resume it
+fn declare(parser: &mut Parser, program: &mut Program) -> Result<(), Error>
+{
+    let name = match String::from_utf8(utf8(parser)?) {
+        Err(_) => return Err(Error::Syntax),
+        Ok(s) => s
+    };
+
+    let exists = program.sections.iter().any(|s| s.name == name);
+    if comma(parser).is_err() {
+        if !exists {
+            program.begin(Section::new(&name, Region::Rom));
+        }
+        parser.enter(program.resume(&name).unwrap());
+        return Ok(());
+    }
+    if exists {
+        return Err(Error::Placement(name));
+    }
+
+    let mut section = Section::new(&name, Region::Rom);
+    let mut bank = None;
+    loop {
+        let attribute = id(parser)?;
+        match attribute.as_str() {
+            "bank" => bank = Some(bracket(parser)?),
+            "align" => {
+                let bits = bracket(parser)?;
+                if bits > 15 {
+                    return Err(Error::Placement(name));
+                }
+                section.align = (1 << bits, 0);
+            },
+            _ => {
+                let (region, bounds) = match attribute.as_str() {
+                    "ROM0" => (Region::Rom, (0x0000, BANK_SIZE as u32)),
+                    "ROMX" => (Region::Rom, (BANK_SIZE as u32, BANKS * BANK_SIZE as u32)),
+                    "WRAM0" => (Region::Wram, (0xC000, 0xD000)),
+                    "WRAMX" => (Region::Wram, (0xD000, 0xE000)),
+                    "HRAM" => (Region::Hram, Region::Hram.bounds()),
+                    "SRAM" => (Region::Sram, Region::Sram.bounds()),
+                    _ => return Err(Error::Syntax)
+                };
+                section.region = region;
+                section.bounds = bounds;
+                if let Some(Token::LeftBracket) = parser.ahead() {
+                    section.origin = Some(bracket(parser)? as u32);
+                }
+            }
+        }
+        if comma(parser).is_err() {
+            break;
+        }
+    }
+
+    // Only the switchable ROM banks can be chosen, where an
+    // address in a bank is kept apart from the bank it is in.
+    let size = BANK_SIZE as u32;
+    if section.banked() {
+        let address = match section.origin.take() {
+            Some(address) if !(size..2 * size).contains(&address) => {
+                return Err(Error::Placement(name));
+            },
+            address => address.map(|a| a - size)
+        };
+        if let Some(bank) = bank {
+            let start = bank as u32 * size;
+            if bank == 0 || bank as u32 >= BANKS {
+                return Err(Error::Placement(name));
+            }
+            section.bounds = (start, start + size);
+            section.origin = address.map(|a| start + a);
+        } else if let Some(address) = address {
+            if address % section.align.0 != 0 {
+                return Err(Error::Placement(name));
+            }
+            section.align = (size, address);
+        }
+    } else if bank.is_some() {
+        return Err(Error::Placement(name));
+    }
+    parser.enter(program.begin(section));
+    Ok(())
+}
+
+fn constant(parser: &mut Parser, name: String, kind: Kind) -> Result<(), Error>
+{
+    let e = expression(parser)?;
+    let value = fit(parser.eval(&e)?, 16)?;
+    parser.define(name, Symbol::value(kind, value))
+}
+
+fn add(parser: &mut Parser) -> Result<Instruction, Error>
+{
+    match parser.ahead() {
+        Some(Token::Register16(Register16::HL)) => {
+            parser.next();
+            comma(parser)?;
+            match parser.ahead() {
+                Some(Token::Register16(Register16::AF)) |
+                Some(Token::Register16(Register16::PC)) => Err(Error::Syntax),
+                Some(Token::Register16(r)) => {
+                    parser.next();
+                    Ok(Instruction::Add(Operand::Register16(Register16::HL), Operand::Register16(r)))
+                },
+                _ => Err(Error::Syntax)
+            }
+        },
+        Some(Token::Register16(Register16::SP)) => {
+            parser.next();
+            comma(parser)?;
+            Ok(Instruction::Add(Operand::Register16(Register16::SP), offset(parser)?))
+        },
+        _ => Ok(Instruction::Add(Operand::Register(Register::A), arith(parser)?))
+    }
+}
+
+fn call(parser: &mut Parser) -> Result<Instruction, Error>
+{
+    Ok(match condition(parser) {
+        None => Instruction::Call_1(word(parser)?),
+        Some(flag) => {
+            comma(parser)?;
+            Instruction::Call_2(flag, word(parser)?)
+        }
+    })
+}
+
+fn jp(parser: &mut Parser) -> Result<Instruction, Error>
+{
+    Ok(match condition(parser) {
+        None => match parser.ahead() {
+            Some(Token::Register16(Register16::HL)) => {
+                parser.next();
+                Instruction::Jp_1(Operand::Indirect16(Register16::HL))
+            },
+            Some(Token::LeftParen) | Some(Token::LeftBracket) => {
+                match indirect(parser)? {
+                    Operand::Indirect16(Register16::HL) => {
+                        Instruction::Jp_1(Operand::Indirect16(Register16::HL))
+                    },
+                    _ => return Err(Error::Syntax)
+                }
+            },
+            _ => Instruction::Jp_1(word(parser)?)
+        },
+        Some(flag) => {
+            comma(parser)?;
+            Instruction::Jp_2(flag, word(parser)?)
+        }
+    })
+}
+
+/// A branch condition, where `c` is the carry flag
+fn condition(parser: &mut Parser) -> Option<Operand>
+{
+    use FlagRegister::*;
+    let flag = match parser.ahead() {
+        Some(Token::Flag(f)) => match f {
+            Z | NZ | CR | NC => f,
+            _ => return None
+        },
+        Some(Token::Register(Register::C)) => CR,
+        _ => return None
+    };
+    parser.next();
+    Some(Operand::Flag(flag))
+}
+
+fn jr(parser: &mut Parser) -> Result<Instruction, Error>
+{
+    Ok(match condition(parser) {
+        None => Instruction::Jr_1(Operand::Expression(expression(parser)?)),
+        Some(flag) => {
+            comma(parser)?;
+            Instruction::Jr_2(flag, Operand::Expression(expression(parser)?))
+        }
+    })
+}
+
+fn ld(parser: &mut Parser) -> Result<Instruction, Error>
+{
+    let dst = operand(parser)?;
+    comma(parser)?;
+    let src = operand(parser)?;
+
+    use Operand::{
+        Register as R,
+        Register16 as R16,
+        Indirect,
+        Indirect16 as I16,
+        Expression,
+        Address,
+        High,
+        IndirectIncrement,
+        IndirectDecrement,
+        Deferred
+    };
+    use Register::{A, C};
+    use Register16::*;
+    let (dst, src) = match (dst, src) {
+        (I16(HL), I16(HL)) => return Err(Error::Syntax),
+        (d @ R(_), s @ R(_)) |
+        (d @ R(_), s @ I16(HL)) |
+        (d @ I16(HL), s @ R(_)) => (d, s),
+        (d @ R(A), s @ I16(BC)) | (d @ R(A), s @ I16(DE)) |
+        (d @ I16(BC), s @ R(A)) | (d @ I16(DE), s @ R(A)) => (d, s),
+        (d @ R(_), s @ Expression(_)) |
+        (d @ I16(HL), s @ Expression(_)) => (d, imm8(parser, s)?),
+        (d @ R16(BC), s) | (d @ R16(DE), s) |
+        (d @ R16(HL), s) | (d @ R16(SP), s) => match s {
+            R16(HL) if d == R16(SP) => (d, s),
+            R16(SP) if d == R16(HL) => match parser.ahead() {
+                Some(Token::Operator(_)) => (d, offset(parser)?),
+                _ => return Err(Error::Syntax)
+            },
+            Expression(_) => (d, imm16(parser, s)?),
+            _ => return Err(Error::Syntax)
+        },
+        (d @ R(A), s @ (Address(_) | Deferred(Width::Address, _))) |
+        (d @ (Address(_) | Deferred(Width::Address, _)), s @ R(A)) |
+        (d @ (Address(_) | Deferred(Width::Address, _)), s @ R16(SP)) => (d, s),
+        (d @ R(A), s @ (High(_) | Deferred(Width::High, _))) |
+        (d @ (High(_) | Deferred(Width::High, _)), s @ R(A)) |
+        (d @ R(A), s @ Indirect(C)) | (d @ Indirect(C), s @ R(A)) => (d, s),
+        (d @ R(A), s @ IndirectIncrement) | (d @ IndirectIncrement, s @ R(A)) |
+        (d @ R(A), s @ IndirectDecrement) | (d @ IndirectDecrement, s @ R(A)) => (d, s),
+        _ => return Err(Error::Syntax)
+    };
+    Ok(Instruction::Ld(dst, src))
+}
+
+/// `ldi` and `ldd`, as `ld` with `(hl+)` or `(hl-)`
+fn ld_hl(parser: &mut Parser, hl: Operand) -> Result<Instruction, Error>
+{
+    let dst = operand(parser)?;
+    comma(parser)?;
+    let src = operand(parser)?;
+
+    use Operand::{
+        Register as R,
+        Indirect16 as I16
+    };
+    match (dst, src) {
+        (R(Register::A), I16(Register16::HL)) => Ok(Instruction::Ld(R(Register::A), hl)),
+        (I16(Register16::HL), R(Register::A)) => Ok(Instruction::Ld(hl, R(Register::A))),
+        _ => Err(Error::Syntax)
+    }
+}
+
+/// Narrow an absolute address operand to the high page
+fn high(operand: Operand) -> Result<Operand, Error>
+{
+    match operand {
+        Operand::Address(u) if u >= 0xFF00 => Ok(Operand::High(u as u8)),
+        Operand::Address(u) => Err(Error::HighRange(u as i32)),
+        Operand::Deferred(Width::Address, e) => Ok(Operand::Deferred(Width::High, e)),
+        _ => Ok(operand)
+    }
+}
+
+fn ldh(parser: &mut Parser) -> Result<Instruction, Error>
+{
+    let dst = operand(parser)?;
+    let dst = high(dst)?;
+    comma(parser)?;
+    let src = operand(parser)?;
+    let src = high(src)?;
+
+    use Operand::{
+        Register as R,
+        Indirect,
+        High,
+        Deferred
+    };
+    use Register::{A, C};
+    match (dst, src) {
+        (d @ R(A), s @ (High(_) | Deferred(Width::High, _))) |
+        (d @ (High(_) | Deferred(Width::High, _)), s @ R(A)) |
+        (d @ R(A), s @ Indirect(C)) | (d @ Indirect(C), s @ R(A)) => {
+            Ok(Instruction::Ld(d, s))
+        },
+        _ => Err(Error::Syntax)
+    }
+}
+
+fn rst(parser: &mut Parser) -> Result<Instruction, Error>
+{
+    let e = expression(parser)?;
+    match eval16(parser, &e)? {
+        v @ (0x00 | 0x08 | 0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38) => {
+            Ok(Instruction::Rst(Operand::Immediate8(v as u8)))
+        },
+        v => Err(Error::Vector(v))
+    }
+}
+
+fn ret(parser: &mut Parser) -> Result<Instruction, Error>
 {
-    let operand = word(parser)?;
-    Ok(Instruction::Call_1(operand))
+    match parser.ahead() {
+        None | Some(Token::Newline) => Ok(Instruction::Ret),
+        _ => match condition(parser) {
+            None => Err(Error::Syntax),
+            Some(flag) => Ok(Instruction::Ret_1(flag))
+        }
+    }
 }
 
-fn jp(parser: &mut Parser) -> Result<Instruction, ()>
+/// The offset of a jump target from the end of the `jr` at `loc`
+fn displacement(parser: &mut Parser, loc: u16, target: &Operand) -> Result<Operand, Error>
 {
-    let operand = word(parser)?;
-    Ok(Instruction::Jp_1(operand))
+    let addr = match target {
+        Operand::Immediate16(u) => *u,
+        Operand::Expression(e) => eval16(parser, e)?,
+        _ => return Err(Error::Syntax)
+    };
+    let name = match target {
+        Operand::Expression(Expr::Symbol(s)) => match s.split_once('@') {
+            Some((reference, _)) => reference.into(),
+            None => s.clone()
+        },
+        _ => format!("{:#06x}", addr)
+    };
+
+    let offset = addr as i32 - (loc as i32 + 2);
+    if offset < i8::MIN as i32 || offset > i8::MAX as i32 {
+        return Err(Error::JumpRange(name, offset));
+    }
+    Ok(Operand::Immediate8(offset as i8 as u8))
 }
 
-fn ret(parser: &mut Parser) -> Result<Instruction, ()>
+/// Evaluate an operand that was deferred by the first pass
+fn resolve(parser: &Parser, operand: &mut Operand) -> Result<(), Error>
 {
-    match parser.ahead() {
-        Some(Token::Newline) => Ok(Instruction::Ret),
-        Some(Token::Flag(f)) => {
-            use FlagRegister::*;
-            match f {
-                Z | NZ | CR | NC => {
-                    parser.next();
-                    Ok(Instruction::Ret_1(Operand::Flag(f)))
-                },
-                _ => Err(())
-            }
-        },
-        _ => Err(())
+    if let Operand::Deferred(width, e) = operand {
+        *operand = narrow(parser, width, e)?;
     }
+    Ok(())
 }
 
-fn ref_operand(parser: &mut Parser, name: &str) -> Operand
+fn ref_labels(parser: &mut Parser, program: &mut Program) -> Result<(), Error>
 {
-    match parser.symbols.get(name) {
-        None => {
-            // TODO
-            unreachable!();
-        },
-        Some(u) => {
-            Operand::Immediate16(*u)
-        }
+    for section in program.sections.iter_mut() {
+        let start = parser.origin(&section.name)? as u16;
+        ref_section(parser, start, section)?;
     }
+    Ok(())
 }
 
-fn ref_labels(parser: &mut Parser, program: &mut Program)
+fn ref_section(parser: &mut Parser, start: u16, section: &mut Section) -> Result<(), Error>
 {
-    for u in program.units.iter_mut() {
+    let mut loc = start;
+    for u in section.units.iter_mut() {
+        let size = u.bytes(loc);
         match u {
             Unit::Instruction(i) => {
                 use Instruction::*;
                 match i {
-                    Call_1(Operand::Symbol(s)) => { *i = Call_1(ref_operand(parser, s)); },
-                    Jp_1(Operand::Symbol(s)) => { *i = Jp_1(ref_operand(parser, s)); },
+                    Jr_1(o) => { *i = Jr_1(displacement(parser, loc, o)?); },
+                    Jr_2(f, o) => { *i = Jr_2(f.clone(), displacement(parser, loc, o)?); },
+                    Adc(a, b) | Add(a, b) | Bit(a, b) |
+                    Call_2(a, b) | Jp_2(a, b) | Ld(a, b) |
+                    Res(a, b) | Sbc(a, b) | Set(a, b) => {
+                        resolve(parser, a)?;
+                        resolve(parser, b)?;
+                    },
+                    And(a) | Call_1(a) | Cp(a) | Jp_1(a) |
+                    Or(a) | Sub(a) | Xor(a) => resolve(parser, a)?,
                     _ => ()
                 }
             },
+            Unit::Directive(Directive::Byte(Some(values))) |
+            Unit::Directive(Directive::Word(values)) |
+            Unit::Directive(Directive::Long(values)) => {
+                for v in values.iter_mut() {
+                    resolve(parser, v)?;
+                }
+            },
             _ => ()
         }
+        loc += size;
+    }
+    Ok(())
+}
+
+/// The most times a program is parsed for its sections to settle
+const PASSES: usize = 8;
+
+/// Parse until the sections settle, then resolve deferred operands
+pub fn parse(includes: HashSet<PathBuf>, tokens: Vec<Token>) -> Result<(Program, Table), Error>
+{
+    let mut origins = HashMap::new();
+    for _ in 0..PASSES {
+        let (mut program, mut parser) = pass(includes.clone(), tokens.clone(), origins)?;
+        program.check()?;
+        program.place()?;
+        origins = program.origins();
+        if origins == parser.origins {
+            parser.sections = Some(program.extents());
+            ref_labels(&mut parser, &mut program)?;
+            return Ok((program, parser.symbols));
+        }
     }
+    Err(Error::Unsettled)
 }
 
-pub fn parse(mut includes: HashSet<PathBuf>, tokens: Vec<Token>) -> Result<(Program, Table), ()>
+/// Parse each unit of a program, given where sections start
+fn pass(mut includes: HashSet<PathBuf>, tokens: Vec<Token>,
+        origins: HashMap<String, (Region, u32)>) -> Result<(Program, Parser), Error>
 {
     let mut program = Program::new();
-    let mut parser = Parser::new(tokens);
+    let mut parser = Parser::new(tokens, origins);
+    parser.enter(program.current());
 
     while let Some(token) = parser.look() {
+        parser.location = program.current().location;
         match token {
             Token::Id(s) => {
                 match parser.ahead() {
                     Some(Token::Colon) => {
-                        if parser.symbols.contains_key(&s) {
-                            // TODO
-                            return Err(());
+                        let s = parser.qualify(s)?;
+                        if !s.contains('.') {
+                            parser.scope = Some(s.clone());
                         }
-                        parser.symbols.insert(s.to_string(), program.location);
+                        parser.define(s, Symbol::label(&program))?;
                         parser.next();
                         parser.next();
                         continue;
                     },
-                    _ => return Err(())
+                    Some(Token::Directive(token::Directive::Equ)) => {
+                        parser.next();
+                        constant(&mut parser, s, Kind::Constant)?;
+                        newline(&mut parser)?;
+                    },
+                    _ => return Err(Error::Syntax)
                 }
             },
             Token::Operation(o) => {
                 use Operation::*;
                 match o {
-                    And  => program.push(Instruction::And(reg_any_reg16_hl(&mut parser)?).into()),
-                    Call => program.push(call(&mut parser)?.into()),
-                    Ccf  => program.push(Instruction::Ccf.into()),
-                    Cpl  => program.push(Instruction::Cpl.into()),
-                    Daa  => program.push(Instruction::Daa.into()),
-                    Dec  => program.push(Instruction::Dec(reg_any_reg16_hl(&mut parser)?).into()),
-                    Di   => program.push(Instruction::Di.into()),
-                    Ei   => program.push(Instruction::Ei.into()),
-                    Halt => program.push(Instruction::Halt.into()),
-                    Inc  => program.push(Instruction::Inc(reg_any_reg16_hl(&mut parser)?).into()),
-                    Jp   => program.push(jp(&mut parser)?.into()),
-                    Nop  => program.push(Instruction::Nop.into()),
-                    Or   => program.push(Instruction::Or(reg_any_reg16_hl(&mut parser)?).into()),
-                    Pop  => program.push(Instruction::Pop(reg16_not_sp_pc(&mut parser)?).into()),
-                    Push => program.push(Instruction::Push(reg16_not_sp_pc(&mut parser)?).into()),
-                    Ret  => program.push(ret(&mut parser)?.into()),
-                    Reti => program.push(Instruction::Reti.into()),
-                    Rlca => program.push(Instruction::Rlca.into()),
-                    Rra  => program.push(Instruction::Rra.into()),
-                    Rrca => program.push(Instruction::Rrca.into()),
-                    Scf  => program.push(Instruction::Scf.into()),
-                    Stop => program.push(Instruction::Stop.into()),
-                    Xor  => program.push(Instruction::Xor(reg_any_reg16_hl(&mut parser)?).into()),
-                    _ => ()
+                    Adc  => program.push(Instruction::Adc(Operand::Register(Register::A), arith(&mut parser)?).into())?,
+                    Add  => program.push(add(&mut parser)?.into())?,
+                    And  => program.push(Instruction::And(arith(&mut parser)?).into())?,
+                    Bit  => {
+                        let (b, r) = bit(&mut parser)?;
+                        program.push(Instruction::Bit(b, r).into())?;
+                    },
+                    Call => program.push(call(&mut parser)?.into())?,
+                    Ccf  => program.push(Instruction::Ccf.into())?,
+                    Cp   => program.push(Instruction::Cp(arith(&mut parser)?).into())?,
+                    Cpl  => program.push(Instruction::Cpl.into())?,
+                    Daa  => program.push(Instruction::Daa.into())?,
+                    Dec  => program.push(Instruction::Dec(inc_dec(&mut parser)?).into())?,
+                    Di   => program.push(Instruction::Di.into())?,
+                    Ei   => program.push(Instruction::Ei.into())?,
+                    Halt => program.push(Instruction::Halt.into())?,
+                    Inc  => program.push(Instruction::Inc(inc_dec(&mut parser)?).into())?,
+                    Jp   => program.push(jp(&mut parser)?.into())?,
+                    Jr   => program.push(jr(&mut parser)?.into())?,
+                    Ld   => program.push(ld(&mut parser)?.into())?,
+                    Ldd  => program.push(ld_hl(&mut parser, Operand::IndirectDecrement)?.into())?,
+                    Ldh  => program.push(ldh(&mut parser)?.into())?,
+                    Ldi  => program.push(ld_hl(&mut parser, Operand::IndirectIncrement)?.into())?,
+                    Nop  => program.push(Instruction::Nop.into())?,
+                    Or   => program.push(Instruction::Or(arith(&mut parser)?).into())?,
+                    Pop  => program.push(Instruction::Pop(reg16_not_sp_pc(&mut parser)?).into())?,
+                    Push => program.push(Instruction::Push(reg16_not_sp_pc(&mut parser)?).into())?,
+                    Ret  => program.push(ret(&mut parser)?.into())?,
+                    Res  => {
+                        let (b, r) = bit(&mut parser)?;
+                        program.push(Instruction::Res(b, r).into())?;
+                    },
+                    Reti => program.push(Instruction::Reti.into())?,
+                    Rl   => program.push(Instruction::Rl(reg_any_reg16_hl(&mut parser)?).into())?,
+                    Rlc  => program.push(Instruction::Rlc(reg_any_reg16_hl(&mut parser)?).into())?,
+                    Rla  => program.push(Instruction::Rla.into())?,
+                    Rlca => program.push(Instruction::Rlca.into())?,
+                    Rr   => program.push(Instruction::Rr(reg_any_reg16_hl(&mut parser)?).into())?,
+                    Rra  => program.push(Instruction::Rra.into())?,
+                    Rrc  => program.push(Instruction::Rrc(reg_any_reg16_hl(&mut parser)?).into())?,
+                    Rrca => program.push(Instruction::Rrca.into())?,
+                    Rst  => program.push(rst(&mut parser)?.into())?,
+                    Sbc  => program.push(Instruction::Sbc(Operand::Register(Register::A), arith(&mut parser)?).into())?,
+                    Scf  => program.push(Instruction::Scf.into())?,
+                    Set  => {
+                        let (b, r) = bit(&mut parser)?;
+                        program.push(Instruction::Set(b, r).into())?;
+                    },
+                    Sla  => program.push(Instruction::Sla(reg_any_reg16_hl(&mut parser)?).into())?,
+                    Sra  => program.push(Instruction::Sra(reg_any_reg16_hl(&mut parser)?).into())?,
+                    Srl  => program.push(Instruction::Srl(reg_any_reg16_hl(&mut parser)?).into())?,
+                    Stop => program.push(Instruction::Stop.into())?,
+                    Sub  => program.push(Instruction::Sub(arith(&mut parser)?).into())?,
+                    Swap => program.push(Instruction::Swap(reg_any_reg16_hl(&mut parser)?).into())?,
+                    Xor  => program.push(Instruction::Xor(arith(&mut parser)?).into())?
                 }
                 newline(&mut parser)?;
             },
@@ -498,92 +1998,119 @@ pub fn parse(mut includes: HashSet<PathBuf>, tokens: Vec<Token>) -> Result<(Prog
                 match d {
                     Direc::Ascii => {
                         let bytes = ascii(&mut parser)?;
-                        program.push(Directive::Ascii(bytes).into());
+                        program.push(Directive::Ascii(bytes).into())?;
                     },
                     Direc::Asciz => {
                         let bytes = ascii(&mut parser)?;
-                        program.push(Directive::Asciz(bytes).into());
+                        program.push(Directive::Asciz(bytes).into())?;
                     },
                     Direc::Byte => {
-                        match byte(&mut parser) {
-                            Err(_) => program.push(Directive::Byte(None).into()),
-                            Ok(b) => {
-                                let mut bytes = vec![b];
-                                while let Ok(_) = comma(&mut parser) {
-                                    let byte = byte(&mut parser)?;
-                                    bytes.push(byte);
-                                }
-                                program.push(Directive::Byte(Some(bytes)).into());
+                        match parser.ahead() {
+                            None | Some(Token::Newline) => program.push(Directive::Byte(None).into())?,
+                            _ => {
+                                let bytes = data(&mut parser, Width::Byte)?;
+                                program.push(Directive::Byte(Some(bytes)).into())?;
                             }
                         }
                     },
+                    Direc::Word => {
+                        let words = data(&mut parser, Width::Word)?;
+                        program.push(Directive::Word(words).into())?;
+                    },
+                    Direc::Long => {
+                        let longs = data(&mut parser, Width::Far)?;
+                        program.push(Directive::Long(longs).into())?;
+                    },
                     Direc::Fill => {
                         let (size, byte) = value_byte(&mut parser)?;
-                        program.push(Directive::Fill(size, byte).into());
+                        program.push(Directive::Fill(size, byte).into())?;
+                    },
+                    Direc::Ds => {
+                        let size = count(&mut parser)?;
+                        program.push(Directive::Space(size).into())?;
+                    },
+                    Direc::Incbin => {
+                        let bytes = incbin(&mut parser, &mut program)?;
+                        let section = program.current();
+                        let room = section.room() as usize;
+                        if bytes.len() > room {
+                            return Err(Error::RegionOverflow(section.region, (bytes.len() - room) as u32));
+                        }
+                        program.push(Directive::Incbin(bytes).into())?;
                     },
                     Direc::Org => {
                         let (pos, byte) = value_byte(&mut parser)?;
-                        if pos >= program.location as usize {
-                            program.push(Directive::Org((pos - program.location as usize), byte).into());
+                        let here = parser.eval(&address(&parser.section, parser.location))? as usize;
+                        if pos >= here {
+                            program.push(Directive::Org(pos - here, byte).into())?;
                         } else {
-                            return Err(());
+                            return Err(Error::Syntax);
                         }
                     },
+                    Direc::Equ => {
+                        let symbol = id(&mut parser)?;
+                        comma(&mut parser)?;
+                        constant(&mut parser, symbol, Kind::Constant)?;
+                    },
                     Direc::Set => {
                         let symbol = id(&mut parser)?;
                         comma(&mut parser)?;
-                        match parser.ahead() {
-                            Some(Token::Value(v)) => {
-                                parser.symbols.insert(symbol, v);
-                                parser.next();
-                            },
-                            _ => return Err(())
-                        }
+                        constant(&mut parser, symbol, Kind::Variable)?;
                     },
                     Direc::Use => {
                         let name = match String::from_utf8(utf8(&mut parser)?) {
-                            Err(_) => return Err(()),
+                            Err(_) => return Err(Error::Syntax),
                             Ok(s) => s
                         };
 
                         let mut path: PathBuf = match crate::path(&name) {
-                            Err(_) => return Err(()),
+                            Err(_) => return Err(Error::Include(name)),
                             Ok(path) => path
                         };
 
                         if includes.contains(&path) {
-                            return Err(());
+                            return Err(Error::Include(name));
                         }
 
                         let read = crate::read_file_token(&name);
+                        program.depend(path.clone());
                         includes.insert(path);
                         if let Ok(tokens) = read {
                             parser.include(tokens, 1);
                             continue;
                         } else {
-                            return Err(());
+                            return Err(Error::Include(name));
                         }
                     },
                     Direc::Utf8 => {
                         let bytes = utf8(&mut parser)?;
-                        program.push(Directive::Utf8(bytes).into());
+                        program.push(Directive::Utf8(bytes).into())?;
                     },
-                    _ => ()
+                    Direc::Text => parser.enter(program.section(TEXT, Region::Rom)),
+                    Direc::Data => parser.enter(program.section("data", Region::Rom)),
+                    Direc::Bss => parser.enter(program.section("bss", Region::Wram)),
+                    Direc::Hram => parser.enter(program.section("hram", Region::Hram)),
+                    Direc::Sram => parser.enter(program.section("sram", Region::Sram)),
+                    Direc::Section => declare(&mut parser, &mut program)?
                 }
                 newline(&mut parser)?;
             },
+            Token::Colon => {
+                parser.anonymous.push((parser.section.clone(), parser.location));
+                parser.next();
+                continue;
+            },
             Token::Newline => {
                 parser.next();
                 continue;
             },
-            _ => return Err(())
+            _ => return Err(Error::Syntax)
         }
 
         parser.next();
     }
 
-    ref_labels(&mut parser, &mut program);
-    Ok((program, parser.symbols))
+    Ok((program, parser))
 }
 
 #[cfg(test)]
@@ -605,7 +2132,803 @@ mod tests {
                 halt
         "#).unwrap();
 
-        let program = parse(input).unwrap();
+        let program = parse(HashSet::new(), input).unwrap();
         println!("{:?}", program);
     }
+
+    #[test]
+    fn load()
+    {
+        let input = token::scan(r#"
+                ld b, c
+                ld a, (hl)
+                ld (hl), 0x10
+                ld hl, 0x8000
+                ld (0xC000), a
+                ld (0xC000), sp
+        "#).unwrap();
+
+        let (program, _) = parse(HashSet::new(), input).unwrap();
+        use Operand::*;
+        use token::{Register as R, Register16 as R16};
+        assert_eq!(program.sections[0].units, vec![
+            Instruction::Ld(Register(R::B), Register(R::C)).into(),
+            Instruction::Ld(Register(R::A), Indirect16(R16::HL)).into(),
+            Instruction::Ld(Indirect16(R16::HL), Immediate8(0x10)).into(),
+            Instruction::Ld(Register16(R16::HL), Immediate16(0x8000)).into(),
+            Instruction::Ld(Address(0xC000), Register(R::A)).into(),
+            Instruction::Ld(Address(0xC000), Register16(R16::SP)).into()
+        ]);
+        assert_eq!(program.location(), 13);
+    }
+
+    #[test]
+    fn load_invalid()
+    {
+        for source in &["ld (hl), (hl)", "ld b, (bc)", "ld a, 0x100", "ld af, 0x10", "ld (0xC000), b"] {
+            let input = token::scan(source).unwrap();
+            assert!(parse(HashSet::new(), input).is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn bit_operands()
+    {
+        let input = token::scan("bit 7, (hl)\nset 0, a\nswap b").unwrap();
+        let (program, _) = parse(HashSet::new(), input).unwrap();
+        assert_eq!(program.location(), 6);
+
+        for source in &["bit 8, a", "res 0x10, b", "set 1, bc", "swap hl", "bit 0, hl"] {
+            let input = token::scan(source).unwrap();
+            assert!(parse(HashSet::new(), input).is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn relative_jump()
+    {
+        let input = token::scan(r#"
+            start:
+                jr nz, start
+                jr c, end
+                jr end
+            end:
+        "#).unwrap();
+
+        let (program, _) = parse(HashSet::new(), input).unwrap();
+        use Operand::*;
+        assert_eq!(program.sections[0].units, vec![
+            Instruction::Jr_2(Flag(FlagRegister::NZ), Immediate8(0xFE)).into(),
+            Instruction::Jr_2(Flag(FlagRegister::CR), Immediate8(0x02)).into(),
+            Instruction::Jr_1(Immediate8(0x00)).into()
+        ]);
+    }
+
+    #[test]
+    fn relative_jump_range()
+    {
+        let input = token::scan(r#"
+            start:
+                jr far
+                .fill 127, 0
+            far:
+        "#).unwrap();
+        assert!(parse(HashSet::new(), input).is_ok());
+
+        let input = token::scan(r#"
+            start:
+                jr far
+                .fill 128, 0
+            far:
+        "#).unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::JumpRange("far".into(), 128)));
+
+        let input = token::scan(r#"
+            back:
+                .fill 127, 0
+                jr back
+        "#).unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::JumpRange("back".into(), -129)));
+    }
+
+    #[test]
+    fn high_page()
+    {
+        let input = token::scan(r#"
+                ldh (0xFF44), a
+                ld a, (0xFF00 + 0x80)
+                ldh a, [c]
+                ld (0xFF00 + c), a
+                rst 0x38
+        "#).unwrap();
+
+        let (program, _) = parse(HashSet::new(), input).unwrap();
+        assert_eq!(program.location(), 7);
+
+        let input = token::scan("ldh (0xC000), a").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::HighRange(0xC000)));
+        let input = token::scan("ld a, (0xFF00 + 0x100)").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::HighRange(0x10000)));
+        let input = token::scan("rst 0x07").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Vector(0x07)));
+    }
+
+    #[test]
+    fn logic_immediate()
+    {
+        let input = token::scan(r#"
+            .set MASK, 0x0F
+                and MASK
+                or 0x80
+                xor a, 0xFF
+                cp 10
+                cp (hl)
+        "#).unwrap();
+
+        let (program, _) = parse(HashSet::new(), input).unwrap();
+        use Operand::*;
+        assert_eq!(program.sections[0].units, vec![
+            Instruction::And(Immediate8(0x0F)).into(),
+            Instruction::Or(Immediate8(0x80)).into(),
+            Instruction::Xor(Immediate8(0xFF)).into(),
+            Instruction::Cp(Immediate8(10)).into(),
+            Instruction::Cp(Indirect16(token::Register16::HL)).into()
+        ]);
+
+        let input = token::scan("cp 256").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Overflow(256, 8)));
+        let input = token::scan("cp hl").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Syntax));
+        let input = token::scan("add a, hl").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Syntax));
+    }
+
+    #[test]
+    fn stack_offset()
+    {
+        let input = token::scan(r#"
+                add sp, -2
+                add sp, 127
+                ld hl, sp+4
+                ld hl, sp - 128
+        "#).unwrap();
+
+        let (program, _) = parse(HashSet::new(), input).unwrap();
+        use Operand::*;
+        use token::Register16 as R16;
+        assert_eq!(program.sections[0].units, vec![
+            Instruction::Add(Register16(R16::SP), Offset(-2)).into(),
+            Instruction::Add(Register16(R16::SP), Offset(127)).into(),
+            Instruction::Ld(Register16(R16::HL), Offset(4)).into(),
+            Instruction::Ld(Register16(R16::HL), Offset(-128)).into()
+        ]);
+        assert_eq!(program.location(), 8);
+
+        let input = token::scan("add sp, 128").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::OffsetRange(128)));
+        let input = token::scan("ld hl, sp-129").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::OffsetRange(-129)));
+        let input = token::scan("ld hl, sp").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Syntax));
+    }
+
+    #[test]
+    fn expressions()
+    {
+        let input = token::scan(r#"
+            .set SCRN0, 0x9800
+            .set row, 2
+            .set col, 3
+            .fill 2 * (1 + 1), 0xF0 | 0x0F
+            .byte 1 << 7, 10 % 3, 7 / 2, 0x1234 >> 8, ~0 & 0xFF, 2 > 1 && 3 <= 3
+                ld hl, SCRN0 + 32*row + col
+                ld a, (SCRN0 + col)
+                ld a, -(-5)
+                add sp, 2 - 4
+                ld hl, sp - 2 + 4
+        "#).unwrap();
+
+        let (program, table) = parse(HashSet::new(), input).unwrap();
+        assert_eq!(table.get("SCRN0"), Some(&Symbol::value(Kind::Variable, 0x9800)));
+        use Operand::*;
+        use token::{Register as R, Register16 as R16};
+        assert_eq!(program.sections[0].units, vec![
+            Directive::Fill(4, 0xFF).into(),
+            Directive::Byte(Some([0x80, 1, 3, 0x12, 0xFF, 1].iter().map(|b| Immediate8(*b)).collect())).into(),
+            Instruction::Ld(Register16(R16::HL), Immediate16(0x9843)).into(),
+            Instruction::Ld(Register(R::A), Address(0x9803)).into(),
+            Instruction::Ld(Register(R::A), Immediate8(5)).into(),
+            Instruction::Add(Register16(R16::SP), Offset(-2)).into(),
+            Instruction::Ld(Register16(R16::HL), Offset(2)).into()
+        ]);
+
+        let input = token::scan(r#"
+                ld a, (1+2)
+                ld b, (2+3)*4
+                ld a, (2+3)*4
+        "#).unwrap();
+
+        let (program, _) = parse(HashSet::new(), input).unwrap();
+        assert_eq!(program.sections[0].units, vec![
+            Instruction::Ld(Register(R::A), Address(3)).into(),
+            Instruction::Ld(Register(R::B), Immediate8(20)).into(),
+            Instruction::Ld(Register(R::A), Immediate8(20)).into()
+        ]);
+
+        for source in &[
+            ".equ wPtr, 0xC000\nld hl, [wPtr]", "ld bc, (0x1234)", "ld sp, (0x1234)",
+            "ld b, [0x10]", "ld c, (1+2)", "ld (hl), [5]", "and [5]", "cp [0x10]", "cp (6)"
+        ] {
+            let input = token::scan(source).unwrap();
+            assert_eq!(parse(HashSet::new(), input), Err(Error::Syntax), "{}", source);
+        }
+    }
+
+    #[test]
+    fn expression_errors()
+    {
+        let input = token::scan(".byte 1 / (2 - 2)").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::DivideByZero));
+        let input = token::scan("ld a, 0x80 + 0x80").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Overflow(256, 8)));
+        let input = token::scan("ld hl, (1 + 2").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Syntax));
+        let input = token::scan("call missing + 1").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Undefined("missing".into())));
+    }
+
+    #[test]
+    fn functions()
+    {
+        let input = token::scan(r#"
+            .set LIMIT, 3
+            start:
+                ld bc, sizeof(data)
+                ld de, startof(data)
+                jp far
+            .org 0x4000, 0
+            far:
+                ld a, bank(far)
+                ld h, high(far)
+                ld l, low(far + 0x123)
+            .data
+            tbl:
+                .byte 1, 2, LIMIT
+        "#).unwrap();
+
+        let (program, table) = parse(HashSet::new(), input).unwrap();
+        use Operand::*;
+        use token::{Register as R, Register16 as R16};
+        assert_eq!(program.sections[0].units[0], Instruction::Ld(Register16(R16::BC), Immediate16(3)).into());
+        assert_eq!(program.sections[0].units[1], Instruction::Ld(Register16(R16::DE), Immediate16(0x4006)).into());
+        assert_eq!(program.sections[0].units[4..7], [
+            Instruction::Ld(Register(R::A), Immediate8(1)).into(),
+            Instruction::Ld(Register(R::H), Immediate8(0x40)).into(),
+            Instruction::Ld(Register(R::L), Immediate8(0x23)).into()
+        ]);
+        assert_eq!(table.get("tbl"), Some(&Symbol {
+            kind: Kind::Label,
+            value: 0,
+            section: Some("data".into())
+        }));
+        assert_eq!(table.get("start").and_then(|s| s.section.clone()), Some("text".into()));
+
+        let input = token::scan(r#"
+            .data
+                .byte 1
+            .text
+                ld bc, sizeof(data)
+            .data
+                .byte 2, 3
+            .text
+                ld de, startof(data)
+                ld hl, sizeof(text)
+        "#).unwrap();
+        let (program, _) = parse(HashSet::new(), input).unwrap();
+        assert_eq!(program.sections[0].units, vec![
+            Instruction::Ld(Register16(R16::BC), Immediate16(3)).into(),
+            Instruction::Ld(Register16(R16::DE), Immediate16(9)).into(),
+            Instruction::Ld(Register16(R16::HL), Immediate16(9)).into()
+        ]);
+
+        let input = token::scan(".set LIMIT, 3\nld a, bank(LIMIT)").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Label("LIMIT".into())));
+        let input = token::scan("ld bc, sizeof(bss)").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Section("bss".into())));
+        let input = token::scan("ld a, size(0)").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Syntax));
+    }
+
+    #[test]
+    fn location()
+    {
+        let input = token::scan(r#"
+            start:
+                nop
+                jr $
+                ld hl, @ + 3
+            .set here, $
+            .fill 0x10 - $, 0
+        "#).unwrap();
+
+        let (program, table) = parse(HashSet::new(), input).unwrap();
+        use Operand::*;
+        use token::Register16 as R16;
+        assert_eq!(program.sections[0].units, vec![
+            Instruction::Nop.into(),
+            Instruction::Jr_1(Immediate8(0xFE)).into(),
+            Instruction::Ld(Register16(R16::HL), Immediate16(6)).into(),
+            Directive::Fill(10, 0).into()
+        ]);
+        assert_eq!(table.get("here"), Some(&Symbol::value(Kind::Variable, 6)));
+        assert_eq!(program.location(), 0x10);
+    }
+
+    #[test]
+    fn forward_references()
+    {
+        let input = token::scan(r#"
+                ld a, count
+                ld hl, table
+                ld (buffer), a
+                ldh (rP1), a
+                ldh a, (0xFF00 + port)
+                add sp, -frame
+                and mask
+                jp nz, done
+                .byte count, high(table), low(table)
+            done:
+            table:
+            .set count, 3
+            .set buffer, 0xC000
+            .set rP1, 0xFF00
+            .set port, 0x44
+            .set frame, 4
+            .set mask, 0x0F
+        "#).unwrap();
+
+        let (program, _) = parse(HashSet::new(), input).unwrap();
+        use Operand::*;
+        use token::{Register as R, Register16 as R16, FlagRegister as F};
+        assert_eq!(program.sections[0].units, vec![
+            Instruction::Ld(Register(R::A), Immediate8(3)).into(),
+            Instruction::Ld(Register16(R16::HL), Immediate16(0x16)).into(),
+            Instruction::Ld(Address(0xC000), Register(R::A)).into(),
+            Instruction::Ld(High(0x00), Register(R::A)).into(),
+            Instruction::Ld(Register(R::A), High(0x44)).into(),
+            Instruction::Add(Register16(R16::SP), Offset(-4)).into(),
+            Instruction::And(Immediate8(0x0F)).into(),
+            Instruction::Jp_2(Flag(F::NZ), Immediate16(0x16)).into(),
+            Directive::Byte(Some(vec![Immediate8(3), Immediate8(0x00), Immediate8(0x16)])).into()
+        ]);
+        assert_eq!(program.location(), 0x16);
+
+        let input = token::scan("ld a, missing").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Undefined("missing".into())));
+        let input = token::scan(".byte 1, nowhere").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Undefined("nowhere".into())));
+        let input = token::scan("ldh (io), a\n.set io, 0x8000").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::HighRange(0x8000)));
+        let input = token::scan("cp big\n.set big, 0x100").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Overflow(0x100, 8)));
+    }
+
+    #[test]
+    fn local_labels()
+    {
+        let input = token::scan(r#"
+            player_update:
+                ld b, 4
+            .loop:
+                dec b
+                jr nz, .loop
+                jr .done
+            .done:
+                ret
+            enemy_update:
+            .loop:
+                jp player_update.loop
+                jr .loop
+        "#).unwrap();
+
+        let (program, table) = parse(HashSet::new(), input).unwrap();
+        use Operand::*;
+        use token::{Register as R, FlagRegister as F};
+        assert_eq!(program.sections[0].units, vec![
+            Instruction::Ld(Register(R::B), Immediate8(4)).into(),
+            Instruction::Dec(Register(R::B)).into(),
+            Instruction::Jr_2(Flag(F::NZ), Immediate8(0xFD)).into(),
+            Instruction::Jr_1(Immediate8(0x00)).into(),
+            Instruction::Ret.into(),
+            Instruction::Jp_1(Immediate16(2)).into(),
+            Instruction::Jr_1(Immediate8(0xFB)).into()
+        ]);
+        assert_eq!(table.get("player_update.loop").map(|s| s.value), Some(2));
+        assert_eq!(table.get("player_update.done").map(|s| s.value), Some(7));
+        assert_eq!(table.get("enemy_update.loop").map(|s| s.value), Some(8));
+        assert_eq!(table.get(".loop"), None);
+
+        let input = token::scan(".loop:\n    nop").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Scope(".loop".into())));
+        let input = token::scan("jr .loop").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Scope(".loop".into())));
+    }
+
+    #[test]
+    fn anonymous_labels()
+    {
+        let input = token::scan(r#"
+            copy:
+                ld b, 4
+            :   ld a, (hl+)
+                ld (de), a
+                inc de
+                dec b
+                jr nz, :-
+                jr z, :+
+                call :++
+            :   ret
+            :   jp :--
+        "#).unwrap();
+
+        let (program, table) = parse(HashSet::new(), input).unwrap();
+        use Operand::*;
+        use token::FlagRegister as F;
+        assert_eq!(program.sections[0].units[5..], [
+            Instruction::Jr_2(Flag(F::NZ), Immediate8(0xFA)).into(),
+            Instruction::Jr_2(Flag(F::Z), Immediate8(0x03)).into(),
+            Instruction::Call_1(Immediate16(0x0E)).into(),
+            Instruction::Ret.into(),
+            Instruction::Jp_1(Immediate16(0x0D)).into()
+        ]);
+        assert_eq!(table.len(), 1);
+
+        let input = token::scan("jr :-").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Undefined(":-".into())));
+        let input = token::scan(":\njr :---").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Undefined(":---".into())));
+        let input = token::scan(":\njr :++\n:").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Undefined(":++".into())));
+    }
+
+    #[test]
+    fn constants()
+    {
+        let input = token::scan(r#"
+            .equ WIDTH, 20
+            HEIGHT equ WIDTH - 2
+            .set row, 0
+            .set row, row + 1
+            start:
+                ld a, WIDTH + HEIGHT
+            size equ $ - start
+        "#).unwrap();
+
+        let (program, table) = parse(HashSet::new(), input).unwrap();
+        use Operand::*;
+        use token::Register as R;
+        assert_eq!(program.sections[0].units, vec![Instruction::Ld(Register(R::A), Immediate8(38)).into()]);
+        assert_eq!(table.get("WIDTH"), Some(&Symbol::value(Kind::Constant, 20)));
+        assert_eq!(table.get("HEIGHT"), Some(&Symbol::value(Kind::Constant, 18)));
+        assert_eq!(table.get("row"), Some(&Symbol::value(Kind::Variable, 1)));
+        assert_eq!(table.get("size"), Some(&Symbol::value(Kind::Constant, 2)));
+        assert_eq!(table.get("start").map(|s| s.kind.clone()), Some(Kind::Label));
+
+        let input = token::scan(r#"
+            .set x, 1
+                .byte x + later - later
+                jr later + x - 1
+            .set x, 2
+            later:
+        "#).unwrap();
+        let (program, _) = parse(HashSet::new(), input).unwrap();
+        assert_eq!(program.sections[0].units, vec![
+            Directive::Byte(Some(vec![Immediate8(1)])).into(),
+            Instruction::Jr_1(Immediate8(0)).into()
+        ]);
+
+        let redefined = |source: &str, kind: Kind| {
+            let input = token::scan(source).unwrap();
+            assert_eq!(parse(HashSet::new(), input), Err(Error::Redefined("X".into(), kind)));
+        };
+        redefined(".equ X, 1\n.equ X, 2", Kind::Constant);
+        redefined("X equ 1\n.set X, 2", Kind::Constant);
+        redefined(".set X, 1\n.equ X, 2", Kind::Variable);
+        redefined(".set X, 1\nX:", Kind::Variable);
+        redefined("X:\n.set X, 1", Kind::Label);
+        redefined("X:\nX:", Kind::Label);
+    }
+
+    #[test]
+    fn negative_values()
+    {
+        let input = token::scan(r#"
+            .equ DOWN, -1
+                ld a, -1
+                ld b, DOWN
+                cp -128
+                ld hl, -2
+                .byte -2, 255
+        "#).unwrap();
+
+        let (program, table) = parse(HashSet::new(), input).unwrap();
+        use Operand::*;
+        use token::{Register as R, Register16 as R16};
+        assert_eq!(program.sections[0].units, vec![
+            Instruction::Ld(Register(R::A), Immediate8(0xFF)).into(),
+            Instruction::Ld(Register(R::B), Immediate8(0xFF)).into(),
+            Instruction::Cp(Immediate8(0x80)).into(),
+            Instruction::Ld(Register16(R16::HL), Immediate16(0xFFFE)).into(),
+            Directive::Byte(Some(vec![Immediate8(0xFE), Immediate8(0xFF)])).into()
+        ]);
+        assert_eq!(table.get("DOWN"), Some(&Symbol::value(Kind::Constant, -1)));
+
+        let input = token::scan("ld a, -129").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Overflow(-129, 8)));
+        let input = token::scan(".byte -200").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Overflow(-200, 8)));
+        let input = token::scan("ld bc, -32769").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Overflow(-32769, 16)));
+        let input = token::scan(".fill -1, 0").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Negative(-1)));
+    }
+
+    #[test]
+    fn data_directives()
+    {
+        let input = token::scan(r#"
+            .equ SIZE, 0x200
+            start:
+                .word SIZE, start
+                .long SIZE, start
+        "#).unwrap();
+
+        let (program, _) = parse(HashSet::new(), input).unwrap();
+        use Operand::*;
+        assert_eq!(program.sections[0].units, vec![
+            Directive::Word(vec![Immediate16(0x200), Immediate16(0)]).into(),
+            Directive::Long(vec![Far(0, 0x200), Far(0, 0)]).into()
+        ]);
+        assert_eq!(program.location(), 10);
+
+        let input = token::scan(".word 0x10000").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Overflow(0x10000, 16)));
+        let input = token::scan(".long 0x1000000").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Overflow(0x1000000, 24)));
+        let input = token::scan("start:\n.long start * 2").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Bank("start".into())));
+        let input = token::scan(".word").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Syntax));
+    }
+
+    #[test]
+    fn incbin()
+    {
+        let path = std::env::temp_dir().join("gb-as-incbin.bin");
+        std::fs::write(&path, (0..16).collect::<Vec<u8>>()).unwrap();
+        let name = path.to_str().unwrap();
+
+        let input = token::scan(&format!(r#"
+            .incbin "{0}"
+            .incbin "{0}", 12
+            .incbin "{0}", 2, 3
+        "#, name)).unwrap();
+
+        let (program, _) = parse(HashSet::new(), input).unwrap();
+        assert_eq!(program.sections[0].units, vec![
+            Directive::Incbin((0..16).collect()).into(),
+            Directive::Incbin(vec![12, 13, 14, 15]).into(),
+            Directive::Incbin(vec![2, 3, 4]).into()
+        ]);
+        assert_eq!(program.location(), 23);
+        assert_eq!(program.dependencies(), std::slice::from_ref(&path));
+
+        let input = token::scan(&format!(".incbin \"{}\", 10, 7", name)).unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::IncludeRange(name.into(), 16)));
+        let input = token::scan(&format!(".incbin \"{}\", 17", name)).unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::IncludeRange(name.into(), 16)));
+        let input = token::scan(".incbin \"missing.bin\"").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Include("missing.bin".into())));
+
+        let path = std::env::temp_dir().join("gb-as-incbin-large.bin");
+        std::fs::write(&path, vec![0; 0x10001]).unwrap();
+        let input = token::scan(&format!("nop\n.incbin \"{}\"", path.to_str().unwrap())).unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::RegionOverflow(Region::Rom, 0x8002)));
+        let input = token::scan(&format!(".section \"F\", ROMX\n.incbin \"{}\"", path.to_str().unwrap())).unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::RegionOverflow(Region::Rom, 0xC001)));
+    }
+
+    #[test]
+    fn sections()
+    {
+        let input = token::scan(r#"
+            .data
+            msg:
+                .byte 1, 2
+            .text
+            start:
+                ld hl, msg
+                ld bc, sizeof(data)
+                jr start
+            .data
+            end:
+                .byte low($), low(startof(data))
+        "#).unwrap();
+
+        let (program, table) = parse(HashSet::new(), input).unwrap();
+        use Operand::*;
+        use token::Register16 as R16;
+        let names: Vec<_> = program.sections.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["text", "data"]);
+        assert_eq!(program.sections[0].units[..2], [
+            Instruction::Ld(Register16(R16::HL), Immediate16(8)).into(),
+            Instruction::Ld(Register16(R16::BC), Immediate16(4)).into()
+        ]);
+        assert_eq!(program.sections[1].units[1], Directive::Byte(Some(vec![
+            Immediate8(10), Immediate8(8)
+        ])).into());
+        assert_eq!(program.sections[0].location(), 8);
+        assert_eq!(program.sections[1].location(), 4);
+        assert_eq!(table.get("end"), Some(&Symbol {
+            kind: Kind::Label,
+            value: 2,
+            section: Some("data".into())
+        }));
+
+        let input = token::scan(r#"
+            nop
+            .data
+            x:
+                .byte 1
+            end equ $
+            .equ X, x
+                .org 0x10, 0xFF
+            y:
+                .byte low(end), X
+        "#).unwrap();
+        let (program, table) = parse(HashSet::new(), input).unwrap();
+        assert_eq!(program.sections[1].start(), 1);
+        assert_eq!(table.get("end").map(|s| s.value), Some(2));
+        assert_eq!(table.get("X").map(|s| s.value), Some(1));
+        assert_eq!(program.sections[1].units[1..], [
+            Directive::Org(0xE, 0xFF).into(),
+            Directive::Byte(Some(vec![Immediate8(2), Immediate8(1)])).into()
+        ]);
+        assert_eq!(table.get("y").map(|s| s.value), Some(0xF));
+
+        let input = token::scan(".data\n.equ X, sizeof(data)").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Unplaced("data".into())));
+    }
+
+    #[test]
+    fn ram_sections()
+    {
+        let input = token::scan(r#"
+            .bss
+            player_x:
+                .ds 1
+            player_y:
+                .ds 1
+            buffer:
+                .ds 0x100
+            .hram
+            frame:
+                .ds 1
+            .sram
+            save:
+                .ds 2
+            .text
+                ld a, (player_y)
+                ldh a, (frame)
+                ld hl, save
+                ld bc, sizeof(bss)
+        "#).unwrap();
+
+        let (program, table) = parse(HashSet::new(), input).unwrap();
+        use Operand::*;
+        use token::{Register as R, Register16 as R16};
+        assert_eq!(program.sections[0].units, vec![
+            Instruction::Ld(Register(R::A), Address(0xC001)).into(),
+            Instruction::Ld(Register(R::A), High(0x80)).into(),
+            Instruction::Ld(Register16(R16::HL), Immediate16(0xA000)).into(),
+            Instruction::Ld(Register16(R16::BC), Immediate16(0x102)).into()
+        ]);
+        assert_eq!(program.sections[1].region(), Region::Wram);
+        assert_eq!(program.sections[1].units[2], Directive::Space(0x100).into());
+        assert_eq!(table.get("frame").and_then(|s| s.section.clone()), Some("hram".into()));
+
+        let input = token::scan(".bss\n.byte 1").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Reserved("bss".into())));
+        let input = token::scan(".hram\n.ds 0x7F\n.ds 2").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::RegionOverflow(Region::Hram, 2)));
+        let input = token::scan(".bss\n.ds 0x8000\n.ds 0x8000").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::RegionOverflow(Region::Wram, 1)));
+        let input = token::scan(".fill 0xFFFF, 0\n.fill 0xFFFF, 0").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::RegionOverflow(Region::Rom, 0xFFFF)));
+    }
+
+    #[test]
+    fn named_sections()
+    {
+        let input = token::scan(r#"
+            .section "Vectors", ROM0[$40]
+                reti
+            .section "Code", ROMX, bank[2], align[8]
+            far:
+                nop
+            .section "Table", ROM0, align[4]
+            table:
+                .byte 1
+            .section "Vars", WRAM0
+            var:
+                .ds 2
+            .text
+                nop
+                ld a, bank(far)
+                ld hl, table
+                ld de, startof(Vectors)
+            .section "Vectors"
+                reti
+        "#).unwrap();
+
+        let (program, _) = parse(HashSet::new(), input).unwrap();
+        use Operand::*;
+        use token::{Register as R, Register16 as R16};
+        assert_eq!(program.sections[0].units[1..], [
+            Instruction::Ld(Register(R::A), Immediate8(2)).into(),
+            Instruction::Ld(Register16(R16::HL), Immediate16(0x10)).into(),
+            Instruction::Ld(Register16(R16::DE), Immediate16(0x40)).into()
+        ]);
+        let starts: Vec<_> = program.sections.iter().map(|s| s.start()).collect();
+        assert_eq!(starts, [0, 0x40, 0x8000, 0x10, 0xC000]);
+        assert_eq!(program.sections[1].location(), 2);
+
+        let input = token::scan(r#"
+            .section "Fixed", ROMX[$4100], bank[5]
+            fixed:
+                nop
+            .section "Pinned", ROMX[$4010]
+            pinned:
+                .fill 0x3FF0, 0
+            .section "Free", ROMX
+            free:
+                nop
+            .text
+                ld hl, fixed
+                ld a, bank(fixed)
+                ld hl, pinned
+                ld a, bank(pinned)
+                ld hl, free
+                ld a, bank(free)
+        "#).unwrap();
+
+        let (program, _) = parse(HashSet::new(), input).unwrap();
+        assert_eq!(program.sections[0].units, vec![
+            Instruction::Ld(Register16(R16::HL), Immediate16(0x4100)).into(),
+            Instruction::Ld(Register(R::A), Immediate8(5)).into(),
+            Instruction::Ld(Register16(R16::HL), Immediate16(0x4010)).into(),
+            Instruction::Ld(Register(R::A), Immediate8(1)).into(),
+            Instruction::Ld(Register16(R16::HL), Immediate16(0x4000)).into(),
+            Instruction::Ld(Register(R::A), Immediate8(1)).into()
+        ]);
+        let starts: Vec<_> = program.sections.iter().map(|s| s.start()).collect();
+        assert_eq!(starts, [0, 0x14100, 0x4010, 0x4000]);
+
+        let input = token::scan(".section \"A\", ROM0[$10]\nnop\n.section \"B\", ROM0[$10]\nnop").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Placement("B".into())));
+        let input = token::scan(".section \"A\", ROM0[$41], align[1]\nnop").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Placement("A".into())));
+        let input = token::scan(".section \"A\", ROMX, bank[0]").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Placement("A".into())));
+        let input = token::scan(".section \"A\", ROM0\n.fill 0x4001, 0").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Placement("A".into())));
+        let input = token::scan(".section \"A\", ROMX[$3FFF], bank[1]").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Placement("A".into())));
+        let input = token::scan(".section \"A\", ROM0, bank[1]").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Placement("A".into())));
+        let input = token::scan(".fill 0x9000, 0").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::RegionOverflow(Region::Rom, 0x1000)));
+        let input = token::scan(".section \"A\", VRAM").unwrap();
+        assert_eq!(parse(HashSet::new(), input), Err(Error::Syntax));
+    }
 }
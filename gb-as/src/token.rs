@@ -1,12 +1,16 @@
 
+
+use std::fmt;
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Token {
     /// A code label
     Id(String),
     /// A value
-    Value(u16),
-    /// A string literal
-    String(String),
+    Value(i32),
+    /// A string literal, as the bytes it encodes and
+    /// whether it is written in ASCII characters only
+    String(Vec<u8>, bool),
     /// An 8-bit register
     Register(Register),
     /// A 16-bit register
@@ -17,12 +21,25 @@ pub enum Token {
     Operation(Operation),
     /// Assembler directive
     Directive(Directive),
+    /// An arithmetic operator
+    Operator(Operator),
     /// Current location
     Location,
     /// A comma separator
     Comma,
     /// A colon
     Colon,
+    /// A reference to an anonymous label, counted
+    /// forwards (`:+`, `:++`) or backwards (`:-`, `:--`)
+    Anonymous(i32),
+    /// An opening parenthesis
+    LeftParen,
+    /// A closing parenthesis
+    RightParen,
+    /// An opening bracket
+    LeftBracket,
+    /// A closing bracket
+    RightBracket,
     /// A newline character
     Newline
 }
@@ -92,6 +109,7 @@ impl From<FlagRegister> for Token {
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Operation {
+    Adc,
     Add,
     And,
     Bit,
@@ -108,6 +126,9 @@ pub enum Operation {
     Jp,
     Jr,
     Ld,
+    Ldd,
+    Ldh,
+    Ldi,
     Nop,
     Or,
     Pop,
@@ -143,19 +164,80 @@ impl From<Operation> for Token {
     }
 }
 
+/// The operators used within expressions
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operator {
+    /// `+`
+    Plus,
+    /// `-`
+    Minus,
+    /// `*`
+    Star,
+    /// `/`
+    Slash,
+    /// `%`
+    Percent,
+    /// `<<`
+    ShiftLeft,
+    /// `>>`
+    ShiftRight,
+    /// `&`
+    Ampersand,
+    /// `|`
+    Pipe,
+    /// `^`
+    Caret,
+    /// `~`
+    Tilde,
+    /// `!`
+    Bang,
+    /// `==`
+    Equal,
+    /// `!=`
+    NotEqual,
+    /// `<`
+    Less,
+    /// `<=`
+    LessEqual,
+    /// `>`
+    Greater,
+    /// `>=`
+    GreaterEqual,
+    /// `&&`
+    DoubleAmpersand,
+    /// `||`
+    DoublePipe
+}
+
+impl From<Operator> for Token {
+    fn from(o: Operator) -> Self
+    {
+        Token::Operator(o)
+    }
+}
+
 /// The assembler directives
 #[derive(Clone, Debug, PartialEq)]
 pub enum Directive {
     Ascii,
     Asciz,
+    Bss,
     Byte,
     Data,
+    Ds,
+    Equ,
     Fill,
+    Hram,
+    Incbin,
+    Long,
     Org,
+    Section,
     Set,
+    Sram,
     Text,
     Use,
-    Utf8
+    Utf8,
+    Word
 }
 
 impl From<Directive> for Token {
@@ -234,9 +316,30 @@ impl Tokenizer {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum Error {
-    Directive
+/// An error found while scanning the source
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// A `.` without a directive name
+    Directive,
+    /// A numeric literal that is malformed or too large
+    Number(String),
+    /// A string or character literal that is malformed
+    Literal,
+    /// A character that does not begin any token
+    Unexpected(char)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        use Error::*;
+        match self {
+            Directive => write!(f, "missing directive name"),
+            Number(literal) => write!(f, "invalid numeric literal `{}`", literal),
+            Literal => write!(f, "malformed string or character literal"),
+            Unexpected(c) => write!(f, "unexpected character `{}`", c)
+        }
+    }
 }
 
 #[inline]
@@ -251,84 +354,183 @@ fn numeric(c: char) -> bool
     c.is_ascii_digit()
 }
 
-#[inline]
-fn base_2(c: char) -> bool
+/// The value of the digits of a literal in the given radix,
+/// where digits may be separated by `_`.
+fn value(literal: &str, digits: &str, radix: u32) -> Result<Token, Error>
 {
-    (c == '0' || c == '1')
+    if digits.is_empty() || digits.starts_with('_') {
+        return Err(Error::Number(literal.into()));
+    }
+
+    let digits: String = digits.chars().filter(|c| *c != '_').collect();
+    match i32::from_str_radix(&digits, radix) {
+        Err(_) => Err(Error::Number(literal.into())),
+        Ok(value) => Ok(Token::Value(value))
+    }
 }
 
-fn binary(tokenizer: &mut Tokenizer) -> Result<Token, ()>
+/// A numeric literal beginning with a digit, either decimal,
+/// `0x` hex, `0b` binary, octal with a leading zero or hex
+/// with a `h` suffix such as `0FFh`.
+fn number(tokenizer: &mut Tokenizer) -> Result<Token, Error>
 {
-    match tokenizer.ahead() {
-        None => return Err(()),
-        Some(c) => if !base_2(c) {
-            return Err(());
-        }
+    tokenizer.read_while(|c| alpha(c) || numeric(c) || c == '_');
+    let literal = std::mem::take(&mut tokenizer.string);
+
+    let s = literal.as_str();
+    if let Some(digits) = s.strip_suffix('h').or_else(|| s.strip_suffix('H')) {
+        value(s, digits, 16)
+    } else if let Some(digits) = s.strip_prefix("0x") {
+        value(s, digits, 16)
+    } else if let Some(digits) = s.strip_prefix("0b") {
+        value(s, digits, 2)
+    } else if s.len() > 1 && s.starts_with('0') {
+        value(s, &s[1..], 8)
+    } else {
+        value(s, s, 10)
     }
+}
 
+/// A numeric literal with a radix prefix, i.e. `$FF`, `%1010` or `&777`
+fn prefixed(tokenizer: &mut Tokenizer, radix: u32) -> Result<Token, Error>
+{
     tokenizer.next();
-    tokenizer.string.clear();
-    tokenizer.read_while(base_2);
-    let num = std::mem::take(&mut tokenizer.string);
-
-    Ok(Token::Value(u16::from_str_radix(&num, 2).unwrap()))
+    tokenizer.read_while(|c| alpha(c) || numeric(c) || c == '_');
+    let digits = std::mem::take(&mut tokenizer.string);
+
+    let prefix = match radix {
+        16 => '$',
+        2 => '%',
+        _ => '&'
+    };
+    value(&format!("{}{}", prefix, digits), &digits, radix)
 }
 
-fn octal(tokenizer: &mut Tokenizer) -> Token
+/// Whether the last token ends an operand, in which case
+/// a following `%` or `&` is an operator and not a prefix.
+fn operand_end(tokens: &[Token]) -> bool
 {
-    tokenizer.string.clear();
-    tokenizer.read_while(numeric);
-    let num = std::mem::take(&mut tokenizer.string);
-    Token::Value(u16::from_str_radix(&num, 8).unwrap())
+    matches!(tokens.last(),
+        Some(Token::Value(_)) | Some(Token::Id(_)) |
+        Some(Token::Location) | Some(Token::Anonymous(_)) |
+        Some(Token::RightParen) | Some(Token::RightBracket))
 }
 
-fn hex(tokenizer: &mut Tokenizer) -> Result<Token, ()>
+/// A colon, or a reference to an anonymous label
+/// if it is followed by a run of `+` or `-`.
+fn anonymous(tokenizer: &mut Tokenizer) -> Token
 {
-    match tokenizer.ahead() {
-        None => return Err(()),
-        Some(c) => if !alpha(c) && !numeric(c) {
-            return Err(());
-        }
+    let sign = match tokenizer.ahead() {
+        Some('+') => 1,
+        Some('-') => -1,
+        _ => return Token::Colon
+    };
+    let c = tokenizer.next();
+
+    let mut count = sign;
+    while tokenizer.ahead() == c {
+        tokenizer.next();
+        count += sign;
     }
-
-    tokenizer.next();
-    tokenizer.string.clear();
-    tokenizer.read_while(|c| alpha(c) || numeric(c));
-    let num = std::mem::take(&mut tokenizer.string);
-
-    Ok(Token::Value(u16::from_str_radix(&num, 16).unwrap()))
+    Token::Anonymous(count)
 }
 
-fn num(tokenizer: &mut Tokenizer) -> Result<Token, ()>
+fn operator(tokenizer: &mut Tokenizer) -> Result<Token, Error>
 {
-    tokenizer.read_while(|c| numeric(c));
-    let num = std::mem::take(&mut tokenizer.string);
-    match num.parse::<u16>() {
-        Err(_) => Err(()),
-        Ok(value) => Ok(Token::Value(value))
+    use Operator::*;
+    let c = tokenizer.read().unwrap();
+    let next = tokenizer.ahead();
+
+    let (op, double) = match (c, next) {
+        ('<', Some('<')) => (ShiftLeft, true),
+        ('>', Some('>')) => (ShiftRight, true),
+        ('<', Some('=')) => (LessEqual, true),
+        ('>', Some('=')) => (GreaterEqual, true),
+        ('=', Some('=')) => (Equal, true),
+        ('!', Some('=')) => (NotEqual, true),
+        ('&', Some('&')) => (DoubleAmpersand, true),
+        ('|', Some('|')) => (DoublePipe, true),
+        ('+', _) => (Plus, false),
+        ('-', _) => (Minus, false),
+        ('*', _) => (Star, false),
+        ('/', _) => (Slash, false),
+        ('%', _) => (Percent, false),
+        ('<', _) => (Less, false),
+        ('>', _) => (Greater, false),
+        ('&', _) => (Ampersand, false),
+        ('|', _) => (Pipe, false),
+        ('^', _) => (Caret, false),
+        ('~', _) => (Tilde, false),
+        ('!', _) => (Bang, false),
+        _ => return Err(Error::Unexpected(c))
+    };
+
+    if double {
+        tokenizer.next();
     }
+    Ok(op.into())
 }
 
-fn string(tokenizer: &mut Tokenizer) -> Result<Token, ()>
+/// The byte given by an escape sequence following a `\`
+fn escape(tokenizer: &mut Tokenizer) -> Result<u8, Error>
 {
-    if Some('\"') != tokenizer.ahead() {
-        tokenizer.next();
-        tokenizer.read_while(|c| c != '"');
-    }
+    Ok(match tokenizer.next() {
+        Some('n') => b'\n',
+        Some('t') => b'\t',
+        Some('0') => 0,
+        Some('\\') => b'\\',
+        Some('"') => b'"',
+        Some('\'') => b'\'',
+        Some('x') => {
+            let mut byte = 0;
+            for _ in 0..2 {
+                let digit = tokenizer.next().and_then(|c| c.to_digit(16)).ok_or(Error::Literal)?;
+                byte = byte * 16 + digit as u8;
+            }
+            byte
+        },
+        _ => return Err(Error::Literal)
+    })
+}
 
-    let s = std::mem::take(&mut tokenizer.string);
+fn string(tokenizer: &mut Tokenizer) -> Result<Token, Error>
+{
+    let mut bytes = Vec::new();
+    let mut ascii = true;
+    loop {
+        match tokenizer.next() {
+            None => return Err(Error::Literal),
+            Some('"') => break,
+            Some('\\') => bytes.push(escape(tokenizer)?),
+            Some(c) => {
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                ascii &= c.is_ascii();
+            }
+        }
+    }
+    Ok(Token::String(bytes, ascii))
+}
 
-    if Some('\"') != tokenizer.ahead() {
-        return Err(());
+/// A character constant, i.e. `'A'` or `'\n'`
+fn character(tokenizer: &mut Tokenizer) -> Result<Token, Error>
+{
+    let value = match tokenizer.next() {
+        None | Some('\'') => return Err(Error::Literal),
+        Some('\\') => escape(tokenizer)? as i32,
+        Some(c) => c as i32
+    };
+
+    if tokenizer.next() != Some('\'') {
+        return Err(Error::Literal);
     }
-    tokenizer.next();
-    Ok(Token::String(s))
+    Ok(Token::Value(value))
 }
 
 
-fn ident(tokenizer: &mut Tokenizer) -> Result<Token, ()>
+fn ident(tokenizer: &mut Tokenizer) -> Result<Token, Error>
 {
-    tokenizer.read_while(|c| alpha(c) || c == '_');
+    tokenizer.read_while(|c| alpha(c) || numeric(c) || c == '_' || c == '.');
     let ident = std::mem::take(&mut tokenizer.string);
 
     use Register::*;
@@ -355,6 +557,7 @@ fn ident(tokenizer: &mut Tokenizer) -> Result<Token, ()>
         "hl" => HL.into(),
         "sp" => SP.into(),
         "pc" => PC.into(),
+        "adc" => Adc.into(),
         "add" => Add.into(),
         "and" => And.into(),
         "bit" => Bit.into(),
@@ -371,6 +574,9 @@ fn ident(tokenizer: &mut Tokenizer) -> Result<Token, ()>
         "jp" => Jp.into(),
         "jr" => Jr.into(),
         "ld" => Ld.into(),
+        "ldd" => Ldd.into(),
+        "ldh" => Ldh.into(),
+        "ldi" => Ldi.into(),
         "nop" => Nop.into(),
         "or" => Or.into(),
         "pop" => Pop.into(),
@@ -384,6 +590,7 @@ fn ident(tokenizer: &mut Tokenizer) -> Result<Token, ()>
         "rlca" => Rlca.into(),
         "rr" => Rr.into(),
         "rra" => Rra.into(),
+        "rrc" => Rrc.into(),
         "rrca" => Rrca.into(),
         "rst" => Rst.into(),
         "sbc" => Sbc.into(),
@@ -396,11 +603,12 @@ fn ident(tokenizer: &mut Tokenizer) -> Result<Token, ()>
         "sub" => Sub.into(),
         "swap" => Swap.into(),
         "xor" => Xor.into(),
+        "equ" => Directive::Equ.into(),
         _ => Token::Id(ident)
     })
 }
 
-fn direc(tokenizer: &mut Tokenizer) -> Result<Token, ()>
+fn direc(tokenizer: &mut Tokenizer) -> Result<Token, Error>
 {
     tokenizer.read_while(|c| alpha(c) || numeric(c) || c == '_');
     let direc = std::mem::take(&mut tokenizer.string);
@@ -409,20 +617,29 @@ fn direc(tokenizer: &mut Tokenizer) -> Result<Token, ()>
     Ok(match direc.as_str() {
         ".ascii" => Ascii.into(),
         ".asciz" => Asciz.into(),
+        ".bss"   => Bss.into(),
         ".byte"  => Byte.into(),
         ".data"  => Data.into(),
+        ".ds"    => Ds.into(),
+        ".equ"   => Equ.into(),
         ".fill"  => Fill.into(),
+        ".hram"  => Hram.into(),
+        ".incbin" => Incbin.into(),
+        ".long" | ".farptr" => Long.into(),
         ".org"   => Org.into(),
+        ".section" => Section.into(),
         ".set"   => Set.into(),
+        ".sram"  => Sram.into(),
         ".text"  => Text.into(),
         ".use"   => Use.into(),
         ".utf8"  => Utf8.into(),
-        "." => return Err(()),
+        ".word" | ".dw" => Word.into(),
+        "." => return Err(Error::Directive),
         _ => Token::Id(direc)
     })
 }
 
-pub fn scan(input: &str) -> Result<Vec<Token>, ()>
+pub fn scan(input: &str) -> Result<Vec<Token>, Error>
 {
     let chars: Vec<_> = input.chars().collect();
     let mut tokenizer = Tokenizer::new(chars);
@@ -456,7 +673,47 @@ pub fn scan(input: &str) -> Result<Vec<Token>, ()>
             },
 
             ':' => {
-                tokens.push(Token::Colon);
+                tokens.push(anonymous(&mut tokenizer));
+            },
+
+            '$' => {
+                tokens.push(match tokenizer.ahead() {
+                    Some(c) if c.is_ascii_hexdigit() => prefixed(&mut tokenizer, 16)?,
+                    _ => Token::Location
+                });
+            },
+
+            '@' => {
+                tokens.push(Token::Location);
+            },
+
+            '%' if !operand_end(&tokens) && tokenizer.ahead().is_some_and(|c| c == '0' || c == '1') => {
+                tokens.push(prefixed(&mut tokenizer, 2)?);
+            },
+
+            '&' if !operand_end(&tokens) && tokenizer.ahead().is_some_and(|c| c.is_digit(8)) => {
+                tokens.push(prefixed(&mut tokenizer, 8)?);
+            },
+
+            '+' | '-' | '*' | '/' | '%' | '<' | '>' |
+            '&' | '|' | '^' | '~' | '!' | '=' => {
+                tokens.push(operator(&mut tokenizer)?);
+            },
+
+            '(' => {
+                tokens.push(Token::LeftParen);
+            },
+
+            ')' => {
+                tokens.push(Token::RightParen);
+            },
+
+            '[' => {
+                tokens.push(Token::LeftBracket);
+            },
+
+            ']' => {
+                tokens.push(Token::RightBracket);
             },
 
             '.' => {
@@ -464,38 +721,23 @@ pub fn scan(input: &str) -> Result<Vec<Token>, ()>
                 tokens.push(token);
             },
 
-            '0' => {
-                    tokens.push(if let Some(c) = tokenizer.ahead() {
-                        match c {
-                            '0'|'1'|'2'|'3'|'4'|'5'|'6'|'7'|'8'|'9' => octal(&mut tokenizer),
-                            'b' => {
-                                tokenizer.next();
-                                binary(&mut tokenizer)?
-                            },
-                            'x' => {
-                                tokenizer.next();
-                                hex(&mut tokenizer)?
-                            },
-                            _ => Token::Value(0)
-                        }
-                    } else {
-                        Token::Value(0)
-                    });
-            },
-
             '"' => {
                 tokens.push(string(&mut tokenizer)?);
             },
 
+            '\'' => {
+                tokens.push(character(&mut tokenizer)?);
+            },
+
             _ => {
                 if alpha(c) || c == '_' {
                     let token = ident(&mut tokenizer)?;
                     tokens.push(token);
                 } else if numeric(c) {
-                    let token = num(&mut tokenizer)?;
+                    let token = number(&mut tokenizer)?;
                     tokens.push(token);
                 } else {
-                    return Err(());
+                    return Err(Error::Unexpected(c));
                 }
             }
         }
@@ -555,4 +797,108 @@ mod tests {
         let tokens = scan(input).unwrap();
         println!("{:?}", tokens);
     }
+
+    #[test]
+    fn local_label()
+    {
+        let tokens = scan("jr nz, .loop\njp player_update.done").unwrap();
+        assert_eq!(tokens, vec![
+            Token::Operation(Operation::Jr), Token::Flag(FlagRegister::NZ), Token::Comma,
+            Token::Id(".loop".into()), Token::Newline,
+            Token::Operation(Operation::Jp), Token::Id("player_update.done".into())
+        ]);
+    }
+
+    #[test]
+    fn anonymous_label()
+    {
+        let tokens = scan(":\njr nz, :-\njp :++ + 1").unwrap();
+        assert_eq!(tokens, vec![
+            Token::Colon, Token::Newline,
+            Token::Operation(Operation::Jr), Token::Flag(FlagRegister::NZ), Token::Comma,
+            Token::Anonymous(-1), Token::Newline,
+            Token::Operation(Operation::Jp), Token::Anonymous(2),
+            Operator::Plus.into(), Token::Value(1)
+        ]);
+    }
+
+    #[test]
+    fn escapes()
+    {
+        let tokens = scan(r#".ascii "say \"hi\"\n\t\\\0\x7F""#).unwrap();
+        assert_eq!(tokens[1], Token::String(b"say \"hi\"\n\t\\\0\x7F".to_vec(), true));
+        let tokens = scan(r#".ascii "\x80\xFF""#).unwrap();
+        assert_eq!(tokens[1], Token::String(vec![0x80, 0xFF], true));
+        let tokens = scan(r#".utf8 "世\xFF""#).unwrap();
+        assert_eq!(tokens[1], Token::String(vec![0xE4, 0xB8, 0x96, 0xFF], false));
+
+        assert!(scan(r#""\q""#).is_err());
+        assert!(scan(r#""\x4""#).is_err());
+        assert!(scan(r#""open"#).is_err());
+    }
+
+    #[test]
+    fn characters()
+    {
+        let tokens = scan(r"cp 'A', '\n', '\'', '\x41'").unwrap();
+        assert_eq!(tokens, vec![
+            Token::Operation(Operation::Cp), Token::Value(0x41), Token::Comma,
+            Token::Value(0x0A), Token::Comma, Token::Value(0x27), Token::Comma,
+            Token::Value(0x41)
+        ]);
+
+        assert!(scan("''").is_err());
+        assert!(scan("'ab'").is_err());
+    }
+
+    #[test]
+    fn numbers()
+    {
+        let tokens = scan("0x1F 0b101 017 42 $C000, %1100_0011, &777, 0FFh 1_000").unwrap();
+        assert_eq!(tokens, vec![
+            Token::Value(0x1F), Token::Value(0b101), Token::Value(0o17),
+            Token::Value(42), Token::Value(0xC000), Token::Comma,
+            Token::Value(0b1100_0011), Token::Comma, Token::Value(0o777),
+            Token::Comma, Token::Value(0xFF), Token::Value(1000)
+        ]);
+
+        let tokens = scan("x %10 & 7 + $ % 2").unwrap();
+        use Operator::*;
+        assert_eq!(tokens, vec![
+            Token::Id("x".into()), Percent.into(), Token::Value(10),
+            Ampersand.into(), Token::Value(7), Plus.into(),
+            Token::Location, Percent.into(), Token::Value(2)
+        ]);
+
+        assert_eq!(scan("0x"), Err(Error::Number("0x".into())));
+        assert_eq!(scan("08"), Err(Error::Number("08".into())));
+        assert_eq!(scan("0x1G"), Err(Error::Number("0x1G".into())));
+        assert_eq!(scan("$1_0000_0000"), Err(Error::Number("$1_0000_0000".into())));
+        assert_eq!(scan("3000000000"), Err(Error::Number("3000000000".into())));
+        assert_eq!(scan("0x_1"), Err(Error::Number("0x_1".into())));
+    }
+
+    #[test]
+    fn operators()
+    {
+        let tokens = scan("1 << 2 >= ~x && y != -w").unwrap();
+        use Operator::*;
+        assert_eq!(tokens, vec![
+            Token::Value(1), ShiftLeft.into(), Token::Value(2),
+            GreaterEqual.into(), Tilde.into(), Token::Id("x".into()),
+            DoubleAmpersand.into(), Token::Id("y".into()),
+            NotEqual.into(), Minus.into(), Token::Id("w".into())
+        ]);
+    }
+
+    #[test]
+    fn location()
+    {
+        let tokens = scan("jr $\n.fill 0x150 - @, 0").unwrap();
+        assert_eq!(tokens, vec![
+            Token::Operation(Operation::Jr), Token::Location, Token::Newline,
+            Token::Directive(Directive::Fill), Token::Value(0x150),
+            Operator::Minus.into(), Token::Location, Token::Comma, Token::Value(0)
+        ]);
+    }
 }
@@ -8,7 +8,9 @@ use serde::{
 const MAGIC: &'static str = "GB-O!";
 const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
-/// A library containing a unit of code
+/// A library containing a unit of code, where each section is
+/// already placed by the assembler. The bytes of a section hold
+/// absolute addresses, so a section cannot be moved when linked.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Library<'a> {
     /// Magic number
@@ -16,20 +18,30 @@ pub struct Library<'a> {
     /// The library version
     version: &'a str,
     /// Section information area
-    section: Section
+    sections: Vec<Section>,
+    /// The symbols that belong to no section, such as constants
+    sym: BTreeMap<Sym, Value>
 }
 
 impl<'a> Library<'a> {
     /// Create a new library
-    pub fn new(section: Section) -> Self
+    pub fn new(sections: Vec<Section>) -> Self
     {
         Self {
             magic: MAGIC,
             version: VERSION,
-            section
+            sections,
+            sym: BTreeMap::new()
         }
     }
 
+    /// Add a symbol that belongs to no section
+    pub fn sym<T>(&mut self, sym: T, value: Value)
+        where T: Into<Sym>
+    {
+        self.sym.insert(sym.into(), value);
+    }
+
     /// Read a library from a sequence of bytes
     pub fn read(bin: &'a [u8]) -> Result<Self, ()>
     {
@@ -49,39 +61,33 @@ impl<'a> Library<'a> {
     }
 }
 
-/// The various sections of the library
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct Section {
-    /// Contains executable code
-    text: Text
-}
-
-impl Section {
-    pub fn new(text: Text) -> Self
-    {
-        Self {
-            text
-        }
-    }
-}
-
 /// Stores the relative address of a section symbol
 pub type Addr = u16;
 
 /// Maps names (symbols) to address's
 pub type Sym = String;
 
-/// The text (code) section
+/// The value of a symbol outside of any section
+pub type Value = i32;
+
+/// A named section of the library, such as `text` or `data`
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct Text {
+pub struct Section {
+    name: String,
+    /// The position the section was placed at, which is its offset
+    /// into the ROM image, or its address for a section in RAM
+    start: u32,
     bin: Vec<u8>,
     sym: BTreeMap<Sym, Addr>
 }
 
-impl Text {
-    pub fn new(bin: Vec<u8>) -> Self
+impl Section {
+    pub fn new<T>(name: T, start: u32, bin: Vec<u8>) -> Self
+        where T: Into<String>
     {
         Self {
+            name: name.into(),
+            start,
             bin,
             sym: BTreeMap::new()
         }
@@ -101,12 +107,18 @@ mod tests {
     #[test]
     fn lib_test()
     {
-        let mut text = Text::new(vec![0x10, 0x10, 0x10]);
+        let mut text = Section::new("text", 0x0000, vec![0x10, 0x10, 0x10]);
         text.sym("_start", 0x01);
-        let sect = Section::new(text);
-        let lib = Library::new(sect);
+        let data = Section::new("data", 0x0003, vec![0x20]);
+        let mut lib = Library::new(vec![text, data]);
+        lib.sym("SIZE", -1);
         let bin = lib.write().unwrap();
         let lib = Library::read(&bin).unwrap();
-        assert_eq!(lib.section.text.bin, [0x10, 0x10, 0x10]);
+        assert_eq!(lib.sections[0].name, "text");
+        assert_eq!(lib.sections[0].bin, [0x10, 0x10, 0x10]);
+        assert_eq!(lib.sections[1].name, "data");
+        assert_eq!(lib.sections[1].start, 0x0003);
+        assert_eq!(lib.sections[1].bin, [0x20]);
+        assert_eq!(lib.sym.get("SIZE"), Some(&-1));
     }
 }